@@ -4,24 +4,79 @@ pub enum Error {
     /// Command did not exit successfully.
     #[error("Command exited with code {code}: {message}")]
     CommandFailure { code: i32, message: String },
+    /// Config file is not valid TOML, or does not match the config format.
+    #[error("Failed to parse config file {}: {source}", path.display())]
+    ConfigParse { path: std::path::PathBuf, source: toml::de::Error },
+    /// Config file could not be read.
+    #[error("Failed to read config file {}: {source}", path.display())]
+    ConfigRead { path: std::path::PathBuf, source: std::io::Error },
+    /// Profile dependencies form a cycle.
+    #[error("Dependency cycle detected: {}", path.join(" -> "))]
+    DependencyCycle { path: Vec<String> },
+    /// Profile is defined in more than one config file.
+    #[error("Profile {name} is defined in both {} and {}", first.display(), second.display())]
+    DuplicateProfile { name: String, first: std::path::PathBuf, second: std::path::PathBuf },
+    /// Environment template is defined in more than one config file.
+    #[error("Environment template {name} is defined in both {} and {}", first.display(), second.display())]
+    DuplicateTemplate { name: String, first: std::path::PathBuf, second: std::path::PathBuf },
+    /// Profile was not fully enabled after its `enable` command ran (e.g. the command failed, or `wait_until`
+    /// or `verify` did not succeed), so it may be partly enabled.
+    #[error("Profile {profile} may be partly enabled: {source}")]
+    EnableIncomplete { profile: String, source: Box<Error> },
     /// Command formatting errors.
     #[error(transparent)]
     Format(#[from] interpolator::Error),
+    /// Args are missing, unknown or invalid.
+    #[error("Invalid args: {}", issues.iter().map(ToString::to_string).collect::<Vec<_>>().join("; "))]
+    InvalidArgs { issues: Vec<crate::validation::ArgumentIssue> },
+    /// Config is not valid.
+    #[error("Config is invalid: {}", issues.iter().map(ToString::to_string).collect::<Vec<_>>().join("; "))]
+    InvalidConfig { issues: Vec<crate::validation::ValidationIssue> },
     /// Environment is not defined for a profile.
     #[error("Environment {environment} not defined for profile {profile}")]
     InvalidEnvironment { environment: String, profile: String },
+    /// Config file includes files with a pattern that is not valid.
+    #[error("Invalid include pattern {pattern} in config file {}: {message}", path.display())]
+    InvalidInclude { path: std::path::PathBuf, pattern: String, message: String },
     /// Profile is not valid.
     #[error("Profile {profile} is invalid: {message}")]
     InvalidProfile { profile: String, message: String },
     /// IO errors.
     #[error(transparent)]
     Io(#[from] std::io::Error),
+    /// Profile name or alias is not defined in the config.
+    #[error("Profile {name} not found")]
+    ProfileNotFound { name: String },
     /// Profile requirements not met.
     #[error("Profile requirements not met: {message}")]
     ProfileRequirementsNotMet { message: String },
+    /// Secret referenced by a parameter could not be resolved.
+    #[error("Failed to resolve secret for parameter {parameter}: {message}")]
+    SecretUnavailable { parameter: String, message: String },
+    /// Step of an execution failed, after rolling back earlier steps.
+    #[error("Failed to {action} profile {profile}: {source}")]
+    StepFailed {
+        profile: String,
+        action: String,
+        source: Box<Error>,
+        rolled_back: Vec<String>,
+        report: Box<crate::report::ExecutionReport>,
+    },
+    /// Command did not exit before the timeout.
+    #[error("Command timed out after {after:?}: {command}")]
+    Timeout { command: String, after: std::time::Duration },
+    /// Command placeholder uses a filter that does not exist.
+    #[error("Unknown filter {filter}, possible values are: sh, pwsh, json, url, raw")]
+    UnknownFilter { filter: String },
     /// Error from creating an `&str` from `&[u8]`.
     #[error(transparent)]
     Utf8(#[from] std::str::Utf8Error),
+    /// Profile was not enabled after running the `enable` command.
+    #[error("Profile {profile} not enabled after {attempts} verification attempt(s)")]
+    VerificationFailed { profile: String, attempts: u32 },
+    /// Condition was not met before the deadline or maximum number of attempts.
+    #[error("Condition not met after {attempts} attempt(s) in {elapsed:?}")]
+    WaitTimeout { attempts: u32, elapsed: std::time::Duration },
 }
 
 /// Crate-level result type that wraps [Error](enum.Error.html).
@@ -0,0 +1,136 @@
+/// Shell families with different quoting rules.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Shell {
+    /// POSIX-compatible shells, like `sh`, `bash` and `zsh`.
+    Posix,
+    /// Windows PowerShell and PowerShell Core (`pwsh`).
+    PowerShell,
+}
+
+impl Shell {
+    /// Determine the shell family from the path of the shell executable.
+    ///
+    /// Shells other than PowerShell are assumed to be POSIX-compatible.
+    pub fn from_path(shell: &str) -> Self {
+        let name = shell.rsplit(['/', '\\']).next().unwrap_or(shell).to_ascii_lowercase();
+        let name = name.strip_suffix(".exe").unwrap_or(&name);
+        if name == "powershell" || name == "pwsh" {
+            Shell::PowerShell
+        } else {
+            Shell::Posix
+        }
+    }
+
+    /// Quote a value so the shell passes it to a command as a single literal argument.
+    pub fn quote(&self, value: &str) -> String {
+        match self {
+            Shell::Posix => sh(value),
+            Shell::PowerShell => pwsh(value),
+        }
+    }
+}
+
+/// Quote a value for POSIX shells.
+///
+/// The value is wrapped in single quotes, in which no characters are special,
+/// and single quotes are replaced with `'\''` (close the quotes, escaped quote, reopen the quotes).
+pub fn sh(value: &str) -> String {
+    format!("'{}'", value.replace('\'', r"'\''"))
+}
+
+/// Quote a value for PowerShell.
+///
+/// The value is wrapped in single quotes (verbatim strings), and single quotes (including the typographic
+/// quotes PowerShell also accepts) are escaped by doubling them.
+pub fn pwsh(value: &str) -> String {
+    let mut quoted = String::with_capacity(value.len() + 2);
+    quoted.push('\'');
+    for c in value.chars() {
+        if matches!(c, '\'' | '\u{2018}' | '\u{2019}' | '\u{201A}' | '\u{201B}') {
+            quoted.push(c);
+        }
+        quoted.push(c);
+    }
+    quoted.push('\'');
+    quoted
+}
+
+/// Escape a value as a JSON string, including the surrounding double quotes.
+pub fn json(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len() + 2);
+    escaped.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if c.is_control() => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped.push('"');
+    escaped
+}
+
+/// Percent-encode a value for use in a URL, e.g. as a query parameter.
+///
+/// Every byte other than ASCII letters, digits, `-`, `.`, `_` and `~` is encoded.
+pub fn url(value: &str) -> String {
+    let mut encoded = String::with_capacity(value.len());
+    for byte in value.bytes() {
+        if byte.is_ascii_alphanumeric() || matches!(byte, b'-' | b'.' | b'_' | b'~') {
+            encoded.push(byte as char);
+        } else {
+            encoded.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    encoded
+}
+
+/// Filters that can be applied to args in command placeholders, e.g. `{ssid|sh}`.
+///
+/// Filters are applied from left to right (e.g. `{ssid|json|sh}` quotes the JSON string for POSIX shells).
+/// Args with filters are not quoted for the environment's shell, as the filters control how they are escaped.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Filter {
+    /// Quote for POSIX shells (see: [`sh`]).
+    Sh,
+    /// Quote for PowerShell (see: [`pwsh`]).
+    Pwsh,
+    /// Escape as a JSON string (see: [`json`]).
+    Json,
+    /// Percent-encode for URLs (see: [`url`]).
+    Url,
+    /// Inject as-is.
+    Raw,
+}
+
+impl Filter {
+    /// Apply the filter to a value.
+    pub fn apply(&self, value: &str) -> String {
+        match self {
+            Filter::Sh => sh(value),
+            Filter::Pwsh => pwsh(value),
+            Filter::Json => json(value),
+            Filter::Url => url(value),
+            Filter::Raw => value.to_owned(),
+        }
+    }
+}
+
+impl std::str::FromStr for Filter {
+    type Err = crate::error::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "sh" => Ok(Filter::Sh),
+            "pwsh" => Ok(Filter::Pwsh),
+            "json" => Ok(Filter::Json),
+            "url" => Ok(Filter::Url),
+            "raw" => Ok(Filter::Raw),
+            _ => Err(crate::error::Error::UnknownFilter { filter: s.to_owned() }),
+        }
+    }
+}
@@ -1,3 +1,9 @@
 pub mod error;
+pub mod escape;
+pub mod parameter;
+pub mod plan;
 pub mod process;
 pub mod profile;
+pub mod redact;
+pub mod report;
+pub mod validation;
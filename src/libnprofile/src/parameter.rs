@@ -0,0 +1,408 @@
+use serde::Deserialize;
+
+/// Types of parameter values.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ParameterType {
+    /// Any string.
+    #[default]
+    String,
+    /// Integer (e.g. `42` or `-1`).
+    Int,
+    /// `true` or `false`.
+    Bool,
+    /// One of the parameter's allowed values.
+    Enum,
+    /// IPv4 or IPv6 address (e.g. `192.168.1.10`).
+    Ip,
+    /// IPv4 or IPv6 network in CIDR notation (e.g. `192.168.1.0/24`).
+    Cidr,
+    /// MAC address, as six pairs of hex digits separated by `:` or `-` (e.g. `00:1a:2b:3c:4d:5e`).
+    Mac,
+    /// Non-empty file system path.
+    Path,
+}
+
+impl std::fmt::Display for ParameterType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ParameterType::String => write!(f, "string"),
+            ParameterType::Int => write!(f, "int"),
+            ParameterType::Bool => write!(f, "bool"),
+            ParameterType::Enum => write!(f, "enum"),
+            ParameterType::Ip => write!(f, "ip"),
+            ParameterType::Cidr => write!(f, "cidr"),
+            ParameterType::Mac => write!(f, "mac"),
+            ParameterType::Path => write!(f, "path"),
+        }
+    }
+}
+
+impl ParameterType {
+    /// Check whether a value is of this type.
+    pub fn matches(&self, value: &str) -> bool {
+        match self {
+            ParameterType::String | ParameterType::Enum => true,
+            ParameterType::Int => value.parse::<i64>().is_ok(),
+            ParameterType::Bool => matches!(value, "true" | "false"),
+            ParameterType::Ip => value.parse::<std::net::IpAddr>().is_ok(),
+            ParameterType::Cidr => value.split_once('/').is_some_and(|(address, prefix)| {
+                match (address.parse::<std::net::IpAddr>(), prefix.parse::<u8>()) {
+                    (Ok(std::net::IpAddr::V4(_)), Ok(prefix)) => prefix <= 32,
+                    (Ok(std::net::IpAddr::V6(_)), Ok(prefix)) => prefix <= 128,
+                    _ => false,
+                }
+            }),
+            ParameterType::Mac => {
+                let parts = value.split([':', '-']).collect::<Vec<_>>();
+                parts.len() == 6
+                    && parts.iter().all(|part| part.len() == 2 && part.chars().all(|c| c.is_ascii_hexdigit()))
+            },
+            ParameterType::Path => !value.is_empty() && !value.contains('\0'),
+        }
+    }
+}
+
+/// External stores that parameter defaults can be read from, for secrets that should not be in the config.
+///
+/// Secrets are resolved when commands are run, only if no arg is provided.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, serde::Serialize)]
+pub enum SecretSource {
+    /// Environment variable with the given name.
+    #[serde(rename = "from_env")]
+    Env(String),
+    /// File with the given path (`~/` expands to the home directory), without trailing newlines.
+    #[serde(rename = "from_file")]
+    File(std::path::PathBuf),
+    /// Standard output of a command, run with the environment's shell, without trailing newlines.
+    #[serde(rename = "from_command")]
+    Command(String),
+}
+
+impl std::fmt::Display for SecretSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SecretSource::Env(name) => write!(f, "from_env: {}", name),
+            SecretSource::File(path) => write!(f, "from_file: {}", path.display()),
+            SecretSource::Command(command) => write!(f, "from_command: {}", command),
+        }
+    }
+}
+
+/// Expand a leading `~` in a path to the home directory of the current user.
+pub(crate) fn expand_home(path: &std::path::Path) -> std::path::PathBuf {
+    let home = std::env::var_os(if cfg!(windows) { "USERPROFILE" } else { "HOME" });
+    match (path.strip_prefix("~"), home) {
+        (Ok(path), Some(home)) => std::path::Path::new(&home).join(path),
+        _ => path.to_owned(),
+    }
+}
+
+impl SecretSource {
+    /// Read the secret for the given parameter.
+    ///
+    /// # Parameters
+    ///
+    /// * `parameter`: Name of the parameter, for errors.
+    /// * `shell`: The shell to run commands with (see: [`crate::process::DEFAULT_SHELL`]).
+    /// * `timeout`: Maximum time commands can run for.
+    ///
+    /// # Errors
+    ///
+    /// [`crate::error::Error::SecretUnavailable`]: If the environment variable is not set, the file cannot be read,
+    /// or the command fails.
+    /// [`crate::error::Error::Timeout`]: If the command does not exit before the timeout.
+    pub fn resolve(
+        &self,
+        parameter: &str,
+        shell: Option<&str>,
+        timeout: Option<std::time::Duration>,
+    ) -> crate::error::Result<String> {
+        let unavailable =
+            |message: String| crate::error::Error::SecretUnavailable { parameter: parameter.to_owned(), message };
+        let secret = match self {
+            SecretSource::Env(name) => {
+                std::env::var(name).map_err(|err| unavailable(format!("environment variable {}: {}", name, err)))?
+            },
+            SecretSource::File(path) => std::fs::read_to_string(expand_home(path))
+                .map_err(|err| unavailable(format!("file {}: {}", path.display(), err)))?,
+            SecretSource::Command(command) => {
+                let result = crate::process::run_command_with_options(
+                    [command.as_str()],
+                    &crate::process::RunOptions { shell, timeout, ..Default::default() },
+                )?;
+                if !result.success() {
+                    return Err(unavailable(format!(
+                        "command exited with code {}",
+                        result.code().map_or("unknown".to_owned(), |code| code.to_string()),
+                    )));
+                }
+                result.stdout()?.into_owned()
+            },
+        };
+        Ok(secret.trim_end_matches(['\r', '\n']).to_owned())
+    }
+}
+
+/// Caches secrets resolved from a [`SecretSource`], so that each source is only resolved once
+/// (e.g. a command is not run again when rolling back) with the [`crate::profile::ExecutionOptions`] it is set on.
+///
+/// Clones share the same secrets. Secrets are never included in the debug output.
+#[derive(Clone, Default)]
+pub struct SecretCache(std::sync::Arc<std::sync::Mutex<std::collections::HashMap<SecretSource, String>>>);
+
+impl std::fmt::Debug for SecretCache {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let secrets = self.0.lock().unwrap_or_else(std::sync::PoisonError::into_inner);
+        f.debug_struct("SecretCache").field("len", &secrets.len()).finish()
+    }
+}
+
+impl SecretCache {
+    /// Create a cache without any secrets.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Get the secret of a source, resolving it if it is not cached yet (see: [`SecretSource::resolve`]).
+    ///
+    /// # Errors
+    ///
+    /// See: [`SecretSource::resolve`].
+    pub fn resolve(
+        &self,
+        source: &SecretSource,
+        parameter: &str,
+        shell: Option<&str>,
+        timeout: Option<std::time::Duration>,
+    ) -> crate::error::Result<String> {
+        if let Some(secret) = self.0.lock().unwrap_or_else(std::sync::PoisonError::into_inner).get(source) {
+            return Ok(secret.to_owned());
+        }
+        let secret = source.resolve(parameter, shell, timeout)?;
+        self.0.lock().unwrap_or_else(std::sync::PoisonError::into_inner).insert(source.to_owned(), secret.to_owned());
+        Ok(secret)
+    }
+}
+
+/// Parameter of a [`crate::profile::ProfileEnvironment`].
+///
+/// In the config, parameters are either a default value, or a table with the parameter's schema:
+///
+/// ```toml
+/// [profiles.envs.linux.parameters]
+/// device = "wifi"
+/// ssid = { required = true, description = "Name of the network", pattern = "[^/]+" }
+/// psk = { required = true, secret = true }
+/// band = { type = "enum", values = ["a", "bg"], default = "a" }
+/// vpn_password = { from_command = "pass show vpn" }
+/// ```
+#[derive(Clone, Debug, Default, serde::Deserialize)]
+#[serde(try_from = "ParameterTemplate")]
+pub struct Parameter {
+    /// Type of the parameter's values.
+    pub kind: ParameterType,
+    /// Value used if no arg is provided.
+    pub default: Option<String>,
+    /// Store the value is read from if no arg is provided, instead of a default.
+    pub source: Option<SecretSource>,
+    /// Whether an arg must be provided (if so, the default is ignored).
+    pub required: bool,
+    /// Description of the parameter, for users.
+    pub description: Option<String>,
+    /// Regular expression values must match in full.
+    pub pattern: Option<regex::Regex>,
+    /// Allowed values (required for [`ParameterType::Enum`]).
+    pub values: Option<Vec<String>>,
+    /// Whether values are sensitive (e.g. passwords), and should not be shown when entered.
+    pub secret: bool,
+}
+
+/// Parameter as written in the config, with every key optional: either a parameter of an environment template,
+/// or a parameter of an environment before the template it extends is applied
+/// (see: [`crate::profile::EnvironmentTemplate`]).
+#[derive(Clone, Debug, Default)]
+pub struct ParameterTemplate {
+    /// See: [`Parameter::kind`].
+    pub kind: Option<ParameterType>,
+    /// See: [`Parameter::default`].
+    pub default: Option<String>,
+    /// See: [`Parameter::source`].
+    pub source: Option<SecretSource>,
+    /// See: [`Parameter::required`].
+    pub required: Option<bool>,
+    /// See: [`Parameter::description`].
+    pub description: Option<String>,
+    /// See: [`Parameter::pattern`].
+    pub pattern: Option<String>,
+    /// See: [`Parameter::values`].
+    pub values: Option<Vec<String>>,
+    /// See: [`Parameter::secret`].
+    pub secret: Option<bool>,
+}
+
+/// Table form of a [`Parameter`] in the config.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ParameterTable {
+    #[serde(rename = "type")]
+    kind: Option<ParameterType>,
+    default: Option<String>,
+    from_env: Option<String>,
+    from_file: Option<std::path::PathBuf>,
+    from_command: Option<String>,
+    required: Option<bool>,
+    description: Option<String>,
+    pattern: Option<String>,
+    values: Option<Vec<String>>,
+    secret: Option<bool>,
+}
+
+impl<'de> serde::Deserialize<'de> for ParameterTemplate {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        struct ParameterVisitor;
+
+        impl<'de> serde::de::Visitor<'de> for ParameterVisitor {
+            type Value = ParameterTemplate;
+
+            fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+                formatter.write_str("a default value or a table with the parameter's schema")
+            }
+
+            fn visit_str<E: serde::de::Error>(self, value: &str) -> Result<Self::Value, E> {
+                Ok(ParameterTemplate { default: Some(value.to_owned()), ..Default::default() })
+            }
+
+            fn visit_map<A: serde::de::MapAccess<'de>>(self, map: A) -> Result<Self::Value, A::Error> {
+                let table = ParameterTable::deserialize(serde::de::value::MapAccessDeserializer::new(map))?;
+                let mut sources = [
+                    table.from_env.map(SecretSource::Env),
+                    table.from_file.map(SecretSource::File),
+                    table.from_command.map(SecretSource::Command),
+                ]
+                .into_iter()
+                .flatten();
+                let source = sources.next();
+                if sources.next().is_some() || (source.is_some() && table.default.is_some()) {
+                    return Err(serde::de::Error::custom(
+                        "only one of default, from_env, from_file and from_command can be set",
+                    ));
+                }
+                Ok(ParameterTemplate {
+                    kind: table.kind,
+                    default: table.default,
+                    source,
+                    required: table.required,
+                    description: table.description,
+                    pattern: table.pattern,
+                    values: table.values,
+                    secret: table.secret,
+                })
+            }
+        }
+
+        deserializer.deserialize_any(ParameterVisitor)
+    }
+}
+
+impl ParameterTemplate {
+    /// Merge a template parameter into this one. Keys set here take precedence, others are taken from the template.
+    ///
+    /// The default and the secret source are one value: setting either replaces both. Setting the value also makes
+    /// a required template parameter optional, unless `required` is set here too.
+    pub fn merge(self, template: &ParameterTemplate) -> Self {
+        let has_value = self.default.is_some() || self.source.is_some();
+        ParameterTemplate {
+            kind: self.kind.or(template.kind),
+            default: if has_value { self.default } else { template.default.clone() },
+            source: if has_value { self.source } else { template.source.clone() },
+            required: if has_value { self.required } else { self.required.or(template.required) },
+            description: self.description.or_else(|| template.description.clone()),
+            pattern: self.pattern.or_else(|| template.pattern.clone()),
+            values: self.values.or_else(|| template.values.clone()),
+            secret: self.secret.or(template.secret),
+        }
+    }
+}
+
+impl TryFrom<ParameterTemplate> for Parameter {
+    type Error = String;
+
+    /// Convert a template parameter into a parameter, compiling its pattern.
+    fn try_from(template: ParameterTemplate) -> Result<Self, Self::Error> {
+        let pattern = template
+            .pattern
+            .map(|pattern| regex::Regex::new(&format!("^(?:{})$", pattern)))
+            .transpose()
+            .map_err(|err| err.to_string())?;
+        Ok(Parameter {
+            kind: template.kind.unwrap_or_default(),
+            default: template.default,
+            source: template.source,
+            required: template.required.unwrap_or_default(),
+            description: template.description,
+            pattern,
+            values: template.values,
+            secret: template.secret.unwrap_or_default(),
+        })
+    }
+}
+
+impl Parameter {
+    /// Get the pattern values must match, as written in the config.
+    pub fn pattern(&self) -> Option<&str> {
+        self.pattern.as_ref().map(|pattern| {
+            let pattern = pattern.as_str();
+            pattern.strip_prefix("^(?:").and_then(|pattern| pattern.strip_suffix(")$")).unwrap_or(pattern)
+        })
+    }
+
+    /// Whether an arg must be provided by the user, as the parameter is required or has no default (or source).
+    pub fn needs_arg(&self) -> bool {
+        self.required || (self.default.is_none() && self.source.is_none())
+    }
+
+    /// Whether values are sensitive, as the parameter is secret or its values are read from a [`SecretSource`].
+    pub fn is_secret(&self) -> bool {
+        self.secret || self.source.is_some()
+    }
+
+    /// Check a value against the parameter's schema.
+    ///
+    /// Returns the reason the value is invalid, if it is.
+    pub fn check(&self, value: &str) -> Option<String> {
+        if !self.kind.matches(value) {
+            return Some(format!("expected a value of type {}", self.kind));
+        }
+        if let Some(values) = self.values.as_ref().filter(|values| !values.iter().any(|allowed| allowed == value)) {
+            return Some(format!("expected one of: {}", values.join(", ")));
+        }
+        if self.pattern.as_ref().is_some_and(|pattern| !pattern.is_match(value)) {
+            return Some(format!("expected a value matching {}", self.pattern().unwrap_or_default()));
+        }
+        None
+    }
+
+    /// Check the parameter's schema is consistent.
+    ///
+    /// Returns the reason the schema is invalid, if it is.
+    pub fn check_schema(&self) -> Option<String> {
+        if self.kind == ParameterType::Enum && self.values.as_ref().is_none_or(Vec::is_empty) {
+            return Some("enum parameters must define their allowed values".to_owned());
+        }
+        if self.required {
+            if let Some(source) = self.source.as_ref() {
+                return Some(format!("required parameters cannot read their value from a secret source ({})", source));
+            }
+        }
+        self.default.as_deref().filter(|_| !self.required).and_then(|default| {
+            self.check(default).map(|reason| {
+                let default = if self.is_secret() { crate::redact::REDACTED } else { default };
+                format!("invalid default value {:?}, {}", default, reason)
+            })
+        })
+    }
+}
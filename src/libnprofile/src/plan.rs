@@ -0,0 +1,365 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::{
+    profile::{ActionOutcome, ExecutionOptions, PreparedCommand, Profile, ProfileConfig},
+    report::{CommandRecorder, ExecutionReport, StepReport},
+    validation::check_unknown_args,
+};
+
+/// Supported profile actions.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ProfileAction {
+    /// Disable the profile and its dependencies.
+    Disable,
+    /// Enable the profile and its dependencies.
+    Enable,
+    /// Reset the profile and its dependencies (disable then re-enable).
+    Reset,
+}
+
+/// Core actions run on a single profile.
+///
+/// User-facing profile actions are sequences of one or more core actions.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StepAction {
+    /// Disable the profile.
+    Disable,
+    /// Enable the profile.
+    Enable,
+}
+
+impl std::fmt::Display for StepAction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StepAction::Disable => write!(f, "disable"),
+            StepAction::Enable => write!(f, "enable"),
+        }
+    }
+}
+
+/// Action to run on a single profile using the given environment.
+#[derive(Clone, Debug)]
+pub struct Step<'a> {
+    /// The profile to run the action on.
+    pub profile: &'a Profile,
+    /// Name of the environment to use.
+    pub environment_name: &'a str,
+    /// The action to run.
+    pub action: StepAction,
+}
+
+impl Step<'_> {
+    /// Prepare the commands that could be run by this step, in order, without running them.
+    ///
+    /// # Errors
+    ///
+    /// See: [`Profile::prepare_enable`] and [`Profile::prepare_disable`].
+    pub fn prepare(
+        &self,
+        args: Option<&HashMap<String, String>>,
+        options: &ExecutionOptions,
+    ) -> crate::error::Result<Vec<PreparedCommand>> {
+        match self.action {
+            StepAction::Enable => self.profile.prepare_enable(self.environment_name, args, options),
+            StepAction::Disable => self.profile.prepare_disable(self.environment_name, args, options),
+        }
+    }
+}
+
+/// Ordered steps for running an action on a profile and its dependencies.
+///
+/// # Examples
+///
+/// ```no_run
+/// use libnprofile::{
+///     plan::{Executor, Plan, ProfileAction},
+///     profile::{ExecutionOptions, ProfileConfig},
+/// };
+///
+/// let config: ProfileConfig = toml::from_str(&std::fs::read_to_string("nprofile.toml").unwrap()).unwrap();
+/// let plan = Plan::new(&config, "wifi", ProfileAction::Enable, Some("linux"), None).unwrap();
+/// for step in plan.steps() {
+///     println!("{} {} using {}", step.action, step.profile.name, step.environment_name);
+/// }
+/// Executor::new(ExecutionOptions::default()).execute(&plan).unwrap();
+/// ```
+#[derive(Clone, Debug)]
+pub struct Plan<'a> {
+    profile: &'a Profile,
+    action: ProfileAction,
+    args: Option<&'a HashMap<String, String>>,
+    steps: Vec<Step<'a>>,
+}
+
+impl<'a> Plan<'a> {
+    /// Plan an action on a profile (by name or alias) and its transitive dependencies.
+    ///
+    /// Dependencies are enabled before the profiles that depend on them, and disabled after them.
+    /// Resetting disables every profile, then re-enables them. Dependencies without an explicit environment,
+    /// and the profile itself, use `environment_name`, or the environment chosen for each profile if it is `None`
+    /// (see: [`Profile::choose_environment`]). Args are checked against the parameters of every profile
+    /// before the plan is returned, so invalid args are reported before any commands are run.
+    ///
+    /// # Errors
+    ///
+    /// [`crate::error::Error::ProfileNotFound`]: If the profile name or alias is not defined.
+    /// [`crate::error::Error::InvalidArgs`]: If a required arg is missing, an arg does not match its parameter,
+    /// or an arg is not a parameter of any of the profiles.
+    /// [`crate::error::Error::InvalidEnvironment`]: If the environment of a step is not defined for its profile.
+    ///
+    /// See also: [`ProfileConfig::resolve_dependencies`].
+    pub fn new(
+        config: &'a ProfileConfig,
+        profile_name: &str,
+        action: ProfileAction,
+        environment_name: Option<&'a str>,
+        args: Option<&'a HashMap<String, String>>,
+    ) -> crate::error::Result<Self> {
+        let profile = config
+            .get_profile(profile_name)
+            .ok_or_else(|| crate::error::Error::ProfileNotFound { name: profile_name.to_owned() })?;
+        let profiles = config.resolve_profile_dependencies(profile)?;
+
+        let enables = profiles
+            .into_iter()
+            .map(|(profile, dependency_environment_name)| Step {
+                profile,
+                environment_name: profile.choose_environment(dependency_environment_name.or(environment_name)),
+                action: StepAction::Enable,
+            })
+            .collect::<Vec<_>>();
+        let disables = enables.iter().rev().map(|step| Step { action: StepAction::Disable, ..step.clone() });
+        let steps = match action {
+            ProfileAction::Disable => disables.collect(),
+            ProfileAction::Enable => enables,
+            ProfileAction::Reset => disables.chain(enables.iter().cloned()).collect(),
+        };
+        let plan = Plan { profile, action, args, steps };
+        plan.validate_args()?;
+        Ok(plan)
+    }
+
+    /// Check the args against the parameters of every profile in the plan.
+    ///
+    /// # Errors
+    ///
+    /// [`crate::error::Error::InvalidArgs`]: If a required arg is missing, an arg does not match its parameter,
+    /// or an arg is not a parameter of any of the profiles.
+    /// [`crate::error::Error::InvalidEnvironment`]: If the environment of a step is not defined for its profile.
+    fn validate_args(&self) -> crate::error::Result<()> {
+        let mut issues = Vec::new();
+        let mut parameters = HashSet::new();
+        let mut checked = HashSet::new();
+        for step in self.steps.iter() {
+            if !checked.insert((step.profile.name.as_str(), step.environment_name)) {
+                continue;
+            }
+            let environment = step.profile.get_environment(step.environment_name)?;
+            issues.extend(step.profile.check_args(environment, self.args));
+            parameters.extend(environment.parameters.iter().flatten().map(|(name, _)| name.as_str()));
+        }
+
+        issues.extend(check_unknown_args(self.args, parameters));
+        if issues.is_empty() {
+            Ok(())
+        } else {
+            Err(crate::error::Error::InvalidArgs { issues })
+        }
+    }
+
+    /// The profile the action was planned for.
+    pub fn profile(&self) -> &'a Profile {
+        self.profile
+    }
+
+    /// The planned action.
+    pub fn action(&self) -> ProfileAction {
+        self.action
+    }
+
+    /// Profile-specific args, passed to every step.
+    pub fn args(&self) -> Option<&'a HashMap<String, String>> {
+        self.args
+    }
+
+    /// The steps to run, in order.
+    pub fn steps(&self) -> &[Step<'a>] {
+        &self.steps
+    }
+}
+
+/// Executes steps in order, and rolls back on failure.
+///
+/// If a step fails, the profiles enabled by earlier steps (and the failing profile, if its `enable` command ran)
+/// are disabled in reverse order. When resetting, the profiles disabled by earlier steps that were not re-enabled
+/// yet are also enabled again. Profiles that were already enabled before execution are left as-is.
+#[derive(Clone, Debug)]
+pub struct Executor {
+    /// Options for running profile commands.
+    pub options: ExecutionOptions,
+    /// Whether to roll back when a step fails.
+    pub rollback: bool,
+}
+
+impl Executor {
+    /// Create an executor that rolls back on failure.
+    pub fn new(options: ExecutionOptions) -> Self {
+        Executor { options, rollback: true }
+    }
+
+    fn run_step(
+        step: &Step<'_>,
+        args: Option<&HashMap<String, String>>,
+        options: &ExecutionOptions,
+    ) -> crate::error::Result<ActionOutcome> {
+        match step.action {
+            StepAction::Enable => {
+                log::info!("Enabling profile {} using environment {}", step.profile.name, step.environment_name);
+                let outcome = step.profile.enable(step.environment_name, args, options)?;
+                match outcome {
+                    ActionOutcome::Changed => log::info!("Enabled profile {}", step.profile.name),
+                    ActionOutcome::Unchanged => log::info!("Profile {} is already enabled", step.profile.name),
+                }
+                Ok(outcome)
+            },
+            StepAction::Disable => {
+                log::info!("Disabling profile {} using environment {}", step.profile.name, step.environment_name);
+                let outcome = step.profile.disable(step.environment_name, args, options)?;
+                match outcome {
+                    ActionOutcome::Changed => log::info!("Disabled profile {}", step.profile.name),
+                    ActionOutcome::Unchanged => log::info!("Profile {} is not enabled", step.profile.name),
+                }
+                Ok(outcome)
+            },
+        }
+    }
+
+    /// Report a step, including the commands it ran.
+    fn report_step(
+        step: &Step<'_>,
+        action: StepAction,
+        result: &crate::error::Result<ActionOutcome>,
+        recorder: &CommandRecorder,
+    ) -> StepReport {
+        StepReport {
+            profile: step.profile.name.to_owned(),
+            environment: step.environment_name.to_owned(),
+            action,
+            skipped: matches!(result, Ok(ActionOutcome::Unchanged)),
+            commands: recorder.take(),
+            error: result.as_ref().err().map(ToString::to_string),
+        }
+    }
+
+    /// Undo the given steps that changed a profile, in reverse order.
+    ///
+    /// Profiles enabled by the steps are disabled. Profiles disabled by the steps are enabled again if the action
+    /// is a reset, unless a later step already re-enabled them (a disable followed by a completed enable of the same
+    /// profile cancel out). Failures are logged, and do not stop the remaining profiles from being rolled back.
+    fn roll_back(
+        action: ProfileAction,
+        changed: &[(&Step<'_>, bool)],
+        args: Option<&HashMap<String, String>>,
+        options: &ExecutionOptions,
+        recorder: &CommandRecorder,
+    ) -> Vec<StepReport> {
+        let mut pending: Vec<&Step<'_>> = Vec::with_capacity(changed.len());
+        for (step, completed) in changed.iter() {
+            let is_same = |other: &&Step<'_>| {
+                other.action == StepAction::Disable
+                    && other.profile.name == step.profile.name
+                    && other.environment_name == step.environment_name
+            };
+            match pending.iter().rposition(is_same) {
+                Some(index) if step.action == StepAction::Enable && *completed => {
+                    pending.remove(index);
+                },
+                _ => pending.push(step),
+            }
+        }
+
+        let mut reports = Vec::with_capacity(pending.len());
+        for step in pending.into_iter().rev() {
+            let (result, rollback_action) = match step.action {
+                StepAction::Enable => {
+                    log::info!(
+                        "Rolling back profile {} using environment {}",
+                        step.profile.name,
+                        step.environment_name
+                    );
+                    (step.profile.revert_enable(step.environment_name, args, options), StepAction::Disable)
+                },
+                StepAction::Disable if action == ProfileAction::Reset => {
+                    log::info!("Re-enabling profile {} using environment {}", step.profile.name, step.environment_name);
+                    (step.profile.enable(step.environment_name, args, options), StepAction::Enable)
+                },
+                StepAction::Disable => continue,
+            };
+            if let Err(err) = result.as_ref() {
+                log::error!("Failed to roll back profile {}: {}", step.profile.name, err);
+            }
+            reports.push(Self::report_step(step, rollback_action, &result, recorder));
+        }
+        reports
+    }
+
+    /// Execute the steps of a plan in order, and report every command that was run.
+    ///
+    /// Commands are recorded with a new [`CommandRecorder`], replacing the recorder in the options (if any).
+    /// Secrets are resolved once for the whole execution (including rollback), with a new
+    /// [`crate::parameter::SecretCache`] replacing the cache in the options (if any).
+    ///
+    /// # Errors
+    ///
+    /// [`crate::error::Error::StepFailed`]: If a step fails, wrapping the error, the names of the profiles that
+    /// were rolled back, and the report of the execution.
+    pub fn execute(&self, plan: &Plan<'_>) -> crate::error::Result<ExecutionReport> {
+        let recorder = CommandRecorder::new();
+        let options = ExecutionOptions {
+            recorder: Some(recorder.clone()),
+            secrets: Some(crate::parameter::SecretCache::new()),
+            ..self.options.clone()
+        };
+        let mut report = ExecutionReport {
+            profile: plan.profile.name.to_owned(),
+            action: plan.action,
+            success: true,
+            steps: Vec::with_capacity(plan.steps.len()),
+            rollback: Vec::new(),
+        };
+        let mut changed = Vec::new();
+        for step in plan.steps.iter() {
+            let result = Self::run_step(step, plan.args, &options);
+            report.steps.push(Self::report_step(step, step.action, &result, &recorder));
+            match result {
+                Ok(ActionOutcome::Changed) => changed.push((step, true)),
+                Ok(ActionOutcome::Unchanged) => {},
+                Err(err) => {
+                    report.success = false;
+                    // The enable command ran, so the profile may be partly enabled
+                    if let crate::error::Error::EnableIncomplete { .. } = err {
+                        changed.push((step, false));
+                    }
+                    if self.rollback {
+                        report.rollback = Self::roll_back(plan.action, &changed, plan.args, &options, &recorder);
+                    }
+                    return Err(crate::error::Error::StepFailed {
+                        profile: step.profile.name.to_owned(),
+                        action: step.action.to_string(),
+                        source: Box::new(err),
+                        rolled_back: report
+                            .rollback
+                            .iter()
+                            .filter(|step| step.error.is_none() && !step.skipped)
+                            .map(|step| step.profile.to_owned())
+                            .collect(),
+                        report: Box::new(report),
+                    });
+                },
+            }
+        }
+        Ok(report)
+    }
+}
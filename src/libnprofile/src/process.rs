@@ -1,4 +1,4 @@
-use std::{borrow::Cow, ops::Deref};
+use std::{borrow::Cow, io::BufRead, ops::Deref, sync::Arc};
 
 /// Default shell for running commands.
 ///
@@ -35,8 +35,104 @@ impl CommandResult {
     pub fn stdout(&self) -> crate::error::Result<Cow<'_, str>> {
         Ok(Cow::Borrowed(std::str::from_utf8(&self.0.stdout).map_err(crate::error::Error::from)?.trim()))
     }
+
+    /// Mask secrets in the captured output.
+    ///
+    /// Output that is not valid UTF-8 is left as-is.
+    pub fn redact(self, redactor: &crate::redact::Redactor) -> Self {
+        let redact = |output: Vec<u8>| match String::from_utf8(output) {
+            Ok(output) => redactor.redact(&output).into_owned().into_bytes(),
+            Err(err) => err.into_bytes(),
+        };
+        CommandResult(std::process::Output {
+            status: self.0.status,
+            stdout: redact(self.0.stdout),
+            stderr: redact(self.0.stderr),
+        })
+    }
+}
+
+/// Output streams of a command.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OutputStream {
+    /// Standard output.
+    Stdout,
+    /// Standard error.
+    Stderr,
+}
+
+impl std::fmt::Display for OutputStream {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            OutputStream::Stdout => write!(f, "stdout"),
+            OutputStream::Stderr => write!(f, "stderr"),
+        }
+    }
+}
+
+/// Callback for each line of command output, called as soon as the line is written.
+///
+/// Lines are passed without the trailing line ending. Clones share the same callback.
+#[derive(Clone)]
+pub struct OutputCallback(Arc<OutputFn>);
+
+type OutputFn = dyn Fn(OutputStream, &str) + Send + Sync;
+
+impl OutputCallback {
+    /// Create a callback from a function.
+    pub fn new<F>(callback: F) -> Self
+    where
+        F: Fn(OutputStream, &str) + Send + Sync + 'static,
+    {
+        OutputCallback(Arc::new(callback))
+    }
+
+    /// Create a callback that logs stdout lines at the info level, and stderr lines at the warn level.
+    pub fn log() -> Self {
+        Self::new(|stream, line| match stream {
+            OutputStream::Stdout => log::info!("{}: {}", stream, line),
+            OutputStream::Stderr => log::warn!("{}: {}", stream, line),
+        })
+    }
+
+    /// Create a callback that masks secrets in lines before passing them to this callback.
+    pub fn redacted(&self, redactor: crate::redact::Redactor) -> Self {
+        let callback = self.clone();
+        Self::new(move |stream, line| callback.call(stream, &redactor.redact(line)))
+    }
+
+    fn call(&self, stream: OutputStream, line: &str) {
+        (self.0)(stream, line)
+    }
+}
+
+impl std::fmt::Debug for OutputCallback {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("OutputCallback").finish_non_exhaustive()
+    }
+}
+
+/// Options for running a system command (see: [`run_command_with_options`]).
+#[derive(Clone, Debug, Default)]
+pub struct RunOptions<'a> {
+    /// The shell to run the command with (see: [`DEFAULT_SHELL`]).
+    pub shell: Option<&'a str>,
+    /// Maximum time the command can run for before it is killed.
+    pub timeout: Option<std::time::Duration>,
+    /// Stream output line-by-line to a callback while the command runs (output is still captured).
+    pub on_output: Option<OutputCallback>,
+    /// Run the command with the stdin, stdout and stderr of the current process, e.g. so it can prompt for a password.
+    ///
+    /// Output is not captured (or streamed), and on timeout only the command itself is killed, as it cannot be
+    /// moved to its own process group without losing access to the terminal.
+    pub interactive: bool,
+    /// Environment variables to set for the command, in addition to those of the current process.
+    pub env: &'a [(String, String)],
 }
 
+/// Interval between checks of whether a command with a timeout has exited.
+const POLL_INTERVAL: std::time::Duration = std::time::Duration::from_millis(10);
+
 /// Run a system command using the specified shell and capture stdin/stdout.
 ///
 /// # Parameters
@@ -52,20 +148,141 @@ where
     S: AsRef<std::ffi::OsStr>,
     I: IntoIterator<Item = S>,
 {
-    let shell = shell.unwrap_or(DEFAULT_SHELL);
+    run_command_with_options(command, &RunOptions { shell, ..Default::default() })
+}
+
+/// Run a system command with the given options and capture stdin/stdout.
+///
+/// If a timeout is set, the command is run in its own process group (on Unix) and the whole group is killed
+/// when the timeout expires, so that commands spawned by the shell do not outlive it.
+/// If an output callback is set, output is passed to it line-by-line while the command runs.
+/// If the command is interactive, it uses the stdin, stdout and stderr of the current process instead.
+///
+/// # Parameters
+///
+/// * `command`: The command to run.
+/// * `options`: Options for running the command.
+///
+/// # Errors
+///
+/// [`crate::error::Error::Timeout`]: when the command does not exit before the timeout.
+/// [`crate::error::Error`]: when the shell command cannot be executed.
+pub fn run_command_with_options<S, I>(command: I, options: &RunOptions<'_>) -> crate::error::Result<CommandResult>
+where
+    S: AsRef<std::ffi::OsStr>,
+    I: IntoIterator<Item = S>,
+{
+    let shell = options.shell.unwrap_or(DEFAULT_SHELL);
     #[cfg(target_family = "unix")]
     let command_arg = "-c";
     #[cfg(target_family = "windows")]
     let command_arg = "-Command";
 
-    Ok(CommandResult(
-        std::process::Command::new(shell).arg(command_arg).args(command).output().map_err(crate::error::Error::from)?,
-    ))
+    let mut process = std::process::Command::new(shell);
+    process.arg(command_arg).args(command).envs(options.env.iter().map(|(name, value)| (name, value)));
+    if options.interactive && options.timeout.is_none() {
+        let status = process.status().map_err(crate::error::Error::from)?;
+        return Ok(CommandResult(std::process::Output { status, stdout: Vec::new(), stderr: Vec::new() }));
+    }
+    if options.timeout.is_none() && options.on_output.is_none() {
+        return Ok(CommandResult(process.output().map_err(crate::error::Error::from)?));
+    }
+
+    #[cfg(target_family = "unix")]
+    if options.timeout.is_some() && !options.interactive {
+        std::os::unix::process::CommandExt::process_group(&mut process, 0);
+    }
+    if !options.interactive {
+        process
+            .stdin(std::process::Stdio::null())
+            .stdout(std::process::Stdio::piped())
+            .stderr(std::process::Stdio::piped());
+    }
+    let mut child = process.spawn().map_err(crate::error::Error::from)?;
+    let stdout = read_to_end_in_background(child.stdout.take(), OutputStream::Stdout, options.on_output.clone());
+    let stderr = read_to_end_in_background(child.stderr.take(), OutputStream::Stderr, options.on_output.clone());
+
+    let started = std::time::Instant::now();
+    let status = loop {
+        if let Some(status) = child.try_wait().map_err(crate::error::Error::from)? {
+            break status;
+        }
+        if let Some(timeout) = options.timeout.filter(|timeout| started.elapsed() >= *timeout) {
+            if options.interactive {
+                let _ = child.kill();
+                let _ = child.wait();
+            } else {
+                kill_process_group(&mut child);
+            }
+            // Output readers are not joined, as processes that escaped the group may still hold the pipes open
+            return Err(crate::error::Error::Timeout {
+                command: process.get_args().last().map(|arg| arg.to_string_lossy().into_owned()).unwrap_or_default(),
+                after: timeout,
+            });
+        }
+        std::thread::sleep(POLL_INTERVAL);
+    };
+
+    Ok(CommandResult(std::process::Output {
+        status,
+        stdout: stdout.join().unwrap_or_default(),
+        stderr: stderr.join().unwrap_or_default(),
+    }))
+}
+
+/// Read a child process output stream to the end on a separate thread.
+///
+/// If a callback is given, each line is passed to it as soon as it is read.
+fn read_to_end_in_background<R>(
+    stream: Option<R>,
+    output_stream: OutputStream,
+    on_output: Option<OutputCallback>,
+) -> std::thread::JoinHandle<Vec<u8>>
+where
+    R: std::io::Read + Send + 'static,
+{
+    std::thread::spawn(move || {
+        let mut buffer = Vec::new();
+        let Some(mut stream) = stream else {
+            return buffer;
+        };
+        let Some(on_output) = on_output else {
+            let _ = stream.read_to_end(&mut buffer);
+            return buffer;
+        };
+
+        let mut reader = std::io::BufReader::new(stream);
+        loop {
+            let start = buffer.len();
+            match reader.read_until(b'\n', &mut buffer) {
+                Ok(0) | Err(_) => break,
+                Ok(_) => {
+                    let line = String::from_utf8_lossy(&buffer[start..]);
+                    on_output.call(output_stream, line.trim_end_matches(['\r', '\n']));
+                },
+            }
+        }
+        buffer
+    })
+}
+
+/// Kill a child process and, on Unix, every process in its process group.
+fn kill_process_group(child: &mut std::process::Child) {
+    #[cfg(target_family = "unix")]
+    if let Ok(pid) = libc::pid_t::try_from(child.id()) {
+        // SAFETY: `kill` has no memory safety requirements, and the child was spawned as a process group leader
+        unsafe {
+            libc::kill(-pid, libc::SIGKILL);
+        }
+    }
+    let _ = child.kill();
+    let _ = child.wait();
 }
 
 /// Run a function repeatedly until it returns `true`.
 ///
 /// Can be used to e.g. wait for a NIC to be enabled before running another command.
+/// Waits indefinitely, see [`WaitFor`] for waiting with a deadline or maximum number of attempts.
 ///
 /// # Parameters
 ///
@@ -77,11 +294,123 @@ where
 /// [`crate::error::Error`]: when the predicate function returns an error.
 pub fn wait_for<F>(predicate: F, sleep_for: Option<u64>) -> crate::error::Result<()>
 where
-    F: Fn() -> crate::error::Result<bool>,
+    F: FnMut() -> crate::error::Result<bool>,
 {
-    let sleep_for = sleep_for.unwrap_or(1);
-    while !predicate()? {
-        std::thread::sleep(std::time::Duration::from_secs(sleep_for));
+    WaitFor::new().interval(std::time::Duration::from_secs(sleep_for.unwrap_or(1))).run(predicate)
+}
+
+/// Builder for running a function repeatedly until it returns `true`, with an optional deadline and
+/// maximum number of attempts.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::time::Duration;
+///
+/// use libnprofile::process::{run_command, WaitFor};
+///
+/// WaitFor::new()
+///     .interval(Duration::from_millis(250))
+///     .backoff(2.0)
+///     .max_interval(Duration::from_secs(2))
+///     .timeout(Duration::from_secs(30))
+///     .run(|| Ok(run_command(["ip route | grep -q default"], None)?.success()))
+///     .unwrap();
+/// ```
+#[derive(Clone, Debug)]
+pub struct WaitFor {
+    interval: std::time::Duration,
+    backoff: f64,
+    max_interval: Option<std::time::Duration>,
+    timeout: Option<std::time::Duration>,
+    max_attempts: Option<u32>,
+}
+
+impl Default for WaitFor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl WaitFor {
+    /// Wait indefinitely, sleeping for 1 second in between invocations.
+    pub fn new() -> Self {
+        WaitFor {
+            interval: std::time::Duration::from_secs(1),
+            backoff: 1.0,
+            max_interval: None,
+            timeout: None,
+            max_attempts: None,
+        }
+    }
+
+    /// Set the time to sleep for in between the first two invocations.
+    pub fn interval(mut self, interval: std::time::Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// Set the factor to multiply the interval by after each invocation (`1.0`, the default, keeps it constant).
+    pub fn backoff(mut self, backoff: f64) -> Self {
+        self.backoff = backoff;
+        self
+    }
+
+    /// Set the maximum time to sleep for in between invocations when backing off.
+    pub fn max_interval(mut self, max_interval: std::time::Duration) -> Self {
+        self.max_interval = Some(max_interval);
+        self
+    }
+
+    /// Set the maximum total time to wait for.
+    pub fn timeout(mut self, timeout: std::time::Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Set the maximum number of invocations.
+    pub fn max_attempts(mut self, max_attempts: u32) -> Self {
+        self.max_attempts = Some(max_attempts);
+        self
+    }
+
+    /// Run a function repeatedly until it returns `true`.
+    ///
+    /// The function is always invoked at least once, and never sleeps past the deadline.
+    ///
+    /// # Errors
+    ///
+    /// [`crate::error::Error::WaitTimeout`]: when the deadline or maximum number of attempts is reached.
+    /// [`crate::error::Error`]: when the predicate function returns an error.
+    pub fn run<F>(&self, mut predicate: F) -> crate::error::Result<()>
+    where
+        F: FnMut() -> crate::error::Result<bool>,
+    {
+        let start = std::time::Instant::now();
+        let deadline = self.timeout.map(|timeout| start + timeout);
+        let mut interval = self.interval;
+        let mut attempts = 0;
+        loop {
+            attempts += 1;
+            if predicate()? {
+                return Ok(());
+            }
+
+            let now = std::time::Instant::now();
+            let remaining = deadline.map(|deadline| deadline.saturating_duration_since(now));
+            if self.max_attempts.is_some_and(|max_attempts| attempts >= max_attempts)
+                || remaining.is_some_and(|remaining| remaining.is_zero())
+            {
+                return Err(crate::error::Error::WaitTimeout { attempts, elapsed: now - start });
+            }
+
+            log::debug!("Condition not met after {} attempt(s), retrying in {:?}", attempts, interval);
+            std::thread::sleep(remaining.map_or(interval, |remaining| remaining.min(interval)));
+            interval =
+                std::time::Duration::try_from_secs_f64(interval.as_secs_f64() * self.backoff).unwrap_or(interval);
+            if let Some(max_interval) = self.max_interval {
+                interval = interval.min(max_interval);
+            }
+        }
     }
-    Ok(())
 }
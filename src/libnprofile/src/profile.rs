@@ -1,34 +1,593 @@
-use std::{borrow::Cow, collections::HashMap, ops::Deref};
+use std::{
+    borrow::Cow,
+    collections::{HashMap, HashSet},
+    ops::Deref,
+    time::Duration,
+};
 
 use serde::Deserialize;
 
+use crate::redact::{Redactor, REDACTED};
+
+/// Deserialize an optional number of seconds (integer or float) into a [`Duration`].
+fn deserialize_seconds<'de, D>(deserializer: D) -> Result<Option<Duration>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let seconds: Option<f64> = Deserialize::deserialize(deserializer)?;
+    seconds.map(|seconds| Duration::try_from_secs_f64(seconds).map_err(serde::de::Error::custom)).transpose()
+}
+
 /// String containing a shell command.
 ///
 /// Has convenience methods for sanitizing and injecting args into commands.
 /// Should not be used outside of [`ProfileEnvironment`].
-#[derive(Debug, Deserialize)]
-pub struct CommandString(String);
+///
+/// In the config, commands are either a string or a table with the command and its options:
+///
+/// ```toml
+/// enable = "nmcli radio wifi on"
+/// disable = { command = "nmcli radio wifi off", timeout = 10 }
+/// ```
+#[derive(Clone, Debug)]
+pub struct CommandString {
+    command: String,
+    timeout: Option<Duration>,
+    interactive: bool,
+}
+
+/// Table form of a [`CommandString`] in the config.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CommandTable {
+    command: String,
+    #[serde(default, deserialize_with = "deserialize_seconds")]
+    timeout: Option<Duration>,
+    #[serde(default)]
+    interactive: bool,
+}
+
+impl<'de> serde::Deserialize<'de> for CommandString {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        struct CommandStringVisitor;
+
+        impl<'de> serde::de::Visitor<'de> for CommandStringVisitor {
+            type Value = CommandString;
+
+            fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+                formatter.write_str("a command string or a table with a command and its options")
+            }
+
+            fn visit_str<E: serde::de::Error>(self, value: &str) -> Result<Self::Value, E> {
+                Ok(CommandString { command: value.to_owned(), timeout: None, interactive: false })
+            }
+
+            fn visit_map<A: serde::de::MapAccess<'de>>(self, map: A) -> Result<Self::Value, A::Error> {
+                let table = CommandTable::deserialize(serde::de::value::MapAccessDeserializer::new(map))?;
+                Ok(CommandString { command: table.command, timeout: table.timeout, interactive: table.interactive })
+            }
+        }
+
+        deserializer.deserialize_any(CommandStringVisitor)
+    }
+}
 
 impl Deref for CommandString {
     type Target = String;
 
     fn deref(&self) -> &Self::Target {
-        &self.0
+        &self.command
     }
 }
 
 impl CommandString {
     /// Sanitize and inject args into the command string.
+    ///
+    /// If `quoting` is set, every value is quoted for the shell, so it is passed to the command as a single
+    /// literal argument. Otherwise values are injected as-is. Values with filters (e.g. `{ssid|url}`,
+    /// see: [`crate::escape::Filter`]) are only escaped by their filters.
+    ///
+    /// # Errors
+    ///
+    /// [`crate::error::Error::UnknownFilter`]: If a placeholder uses a filter that does not exist.
+    /// [`crate::error::Error::Format`]: If args cannot be injected into the command.
     pub fn prepare_with_args(
         &self,
-        args: Option<&HashMap<&str, interpolator::Formattable<'_>>>,
+        args: Option<&HashMap<&str, &str>>,
+        quoting: Option<crate::escape::Shell>,
     ) -> crate::error::Result<String> {
-        let command = if let Some(args) = args {
-            interpolator::format(self.0.as_str(), args).map_err(crate::error::Error::from)?
-        } else {
-            self.0.clone()
+        let Some(args) = args else {
+            return Ok(self.command.clone());
+        };
+
+        let mut values = HashMap::new();
+        for placeholder in self.placeholder_keys() {
+            let mut parts = placeholder.split('|');
+            let Some(value) = parts.next().and_then(|name| args.get(name.trim())) else {
+                continue;
+            };
+            let filters = parts.map(|filter| filter.trim().parse()).collect::<crate::error::Result<Vec<_>>>()?;
+            let value = match quoting {
+                _ if !filters.is_empty() => {
+                    filters.iter().fold(Cow::Borrowed(*value), |value, filter: &crate::escape::Filter| {
+                        Cow::Owned(filter.apply(&value))
+                    })
+                },
+                Some(shell) => Cow::Owned(shell.quote(value)),
+                None => Cow::Borrowed(*value),
+            };
+            values.insert(placeholder, value);
+        }
+        let values = values
+            .iter()
+            .map(|(key, value)| (*key, interpolator::Formattable::display(value)))
+            .collect::<HashMap<_, _>>();
+        interpolator::format(self.command.as_str(), &values).map_err(crate::error::Error::from)
+    }
+
+    /// Get the maximum time the command can run for, if set for the command.
+    pub fn timeout(&self) -> Option<Duration> {
+        self.timeout
+    }
+
+    /// Check whether the command is run with the terminal's stdin, stdout and stderr instead of capturing them.
+    ///
+    /// Only applies to the `enable` and `disable` commands.
+    pub fn interactive(&self) -> bool {
+        self.interactive
+    }
+
+    /// Get the keys of the placeholders in the command, in order of appearance.
+    ///
+    /// Placeholders use the [`interpolator`] syntax, i.e. `{key}` or `{key:format}`, and literal brackets are
+    /// escaped as `{{` and `}}`. Keys are the parameter name, optionally followed by filters (e.g. `name|sh`).
+    fn placeholder_keys(&self) -> Vec<&str> {
+        let mut keys = Vec::new();
+        let mut rest = self.command.as_str();
+        while let Some(start) = rest.find(['{', '}']) {
+            if rest[start..].starts_with("{{") || rest[start..].starts_with("}}") {
+                rest = &rest[start + 2..];
+            } else if rest[start..].starts_with('}') {
+                rest = &rest[start + 1..];
+            } else if let Some(end) = rest[start + 1..].find('}') {
+                let placeholder = &rest[start + 1..start + 1 + end];
+                let key = placeholder.split(':').next().unwrap_or(placeholder).trim_start();
+                if !keys.contains(&key) {
+                    keys.push(key);
+                }
+                rest = &rest[start + end + 2..];
+            } else {
+                break;
+            }
+        }
+        keys
+    }
+
+    /// Get the names of the parameters referenced by the command, in order of appearance.
+    pub fn placeholders(&self) -> Vec<&str> {
+        let mut placeholders = Vec::new();
+        for key in self.placeholder_keys() {
+            let name = key.split('|').next().unwrap_or(key).trim();
+            if !placeholders.contains(&name) {
+                placeholders.push(name);
+            }
+        }
+        placeholders
+    }
+
+    /// Get the names of the filters used by the command's placeholders, in order of appearance.
+    pub fn filters(&self) -> Vec<&str> {
+        self.placeholder_keys().into_iter().flat_map(|key| key.split('|').skip(1).map(str::trim)).collect()
+    }
+}
+
+/// Phases of enabling or disabling a profile, each of which runs one of the [`ProfileEnvironment`] commands.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CommandPhase {
+    /// Check whether the profile can be enabled.
+    CanEnable,
+    /// Check whether the profile is already enabled.
+    IsEnabled,
+    /// Enable the profile.
+    Enable,
+    /// Disable the profile.
+    Disable,
+    /// Wait until the profile is ready after enabling it.
+    WaitUntil,
+    /// Verify the profile is enabled after enabling it.
+    Verify,
+}
+
+impl std::fmt::Display for CommandPhase {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CommandPhase::CanEnable => write!(f, "can_enable"),
+            CommandPhase::IsEnabled => write!(f, "is_enabled"),
+            CommandPhase::Enable => write!(f, "enable"),
+            CommandPhase::Disable => write!(f, "disable"),
+            CommandPhase::WaitUntil => write!(f, "wait_until"),
+            CommandPhase::Verify => write!(f, "verify"),
+        }
+    }
+}
+
+/// Whether a profile is enabled, as reported by the `is_enabled` command.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ProfileStatus {
+    /// The `is_enabled` command exited successfully.
+    Enabled,
+    /// The `is_enabled` command exited with a non-zero code.
+    Disabled,
+    /// The environment does not define an `is_enabled` command.
+    Unknown,
+}
+
+impl std::fmt::Display for ProfileStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ProfileStatus::Enabled => write!(f, "enabled"),
+            ProfileStatus::Disabled => write!(f, "disabled"),
+            ProfileStatus::Unknown => write!(f, "unknown"),
+        }
+    }
+}
+
+/// Outcome of enabling or disabling a profile.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ActionOutcome {
+    /// The `enable` or `disable` command was run.
+    Changed,
+    /// The profile was already enabled (or not enabled, when disabling), so no command was run.
+    Unchanged,
+}
+
+/// Command with args injected, ready to be run.
+#[derive(Debug)]
+pub struct PreparedCommand {
+    /// Phase the command is run in.
+    pub phase: CommandPhase,
+    /// Rendered command, with the args of secret parameters masked.
+    pub command: String,
+    /// Shell the command is run with.
+    pub shell: String,
+}
+
+/// Prefix of the environment variables that parameters are passed to commands as (see: [`parameter_variable`]).
+pub const PARAMETER_VARIABLE_PREFIX: &str = "NPROFILE_PARAM_";
+
+/// Get the name of the environment variable a parameter is passed to commands as.
+///
+/// The name is the parameter name in upper case, with characters other than ASCII letters and digits replaced by `_`,
+/// prefixed with [`PARAMETER_VARIABLE_PREFIX`] (e.g. `NPROFILE_PARAM_SSID` for `ssid`).
+pub fn parameter_variable(parameter: &str) -> String {
+    let name = parameter
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c.to_ascii_uppercase() } else { '_' })
+        .collect::<String>();
+    format!("{}{}", PARAMETER_VARIABLE_PREFIX, name)
+}
+
+/// How args are injected into commands.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Interpolation {
+    /// Args are quoted for the environment's shell, so each is passed to the command as a single literal argument.
+    #[default]
+    Quoted,
+    /// Args are injected as-is.
+    ///
+    /// Unsafe with untrusted args, as they can inject shell syntax (e.g. `"; rm -rf ~`).
+    Raw,
+}
+
+/// Options for running profile commands.
+#[derive(Clone, Debug, Default)]
+pub struct ExecutionOptions {
+    /// Maximum time each command can run for, unless set for the command or environment.
+    pub timeout: Option<Duration>,
+    /// How args are injected into commands, unless set for the environment (defaults to [`Interpolation::Quoted`]).
+    pub interpolation: Option<Interpolation>,
+    /// Records every command that is run, if set.
+    pub recorder: Option<crate::report::CommandRecorder>,
+    /// Streams command output line-by-line while commands run, if set.
+    pub on_output: Option<crate::process::OutputCallback>,
+    /// Caches secrets read from parameter sources, so each is only resolved once, if set.
+    pub secrets: Option<crate::parameter::SecretCache>,
+}
+
+/// Default maximum time to poll the [`WaitUntil`] command for.
+const DEFAULT_WAIT_UNTIL_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Command polled after enabling a profile until it succeeds.
+///
+/// Can be used to e.g. wait until a DHCP lease is acquired before enabling profiles that depend on the connection.
+///
+/// ```toml
+/// [profiles.envs.linux-nmcli.wait_until]
+/// command = "ip -4 addr show {device} | grep -q inet"
+/// interval = 0.5
+/// backoff = 2.0
+/// max_interval = 4
+/// timeout = 30
+/// ```
+#[derive(Clone, Debug, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct WaitUntil {
+    /// Command to poll.
+    pub command: CommandString,
+    /// Time (in seconds) to sleep for in between the first two attempts (defaults to 1).
+    #[serde(default, deserialize_with = "deserialize_seconds")]
+    pub interval: Option<Duration>,
+    /// Factor to multiply the interval by after each attempt (defaults to 1, i.e. constant interval).
+    pub backoff: Option<f64>,
+    /// Maximum time (in seconds) to sleep for in between attempts when backing off.
+    #[serde(default, deserialize_with = "deserialize_seconds")]
+    pub max_interval: Option<Duration>,
+    /// Maximum total time (in seconds) to wait for (defaults to 60).
+    #[serde(default, deserialize_with = "deserialize_seconds")]
+    pub timeout: Option<Duration>,
+    /// Maximum number of attempts.
+    pub max_attempts: Option<u32>,
+}
+
+impl WaitUntil {
+    /// Get the [`crate::process::WaitFor`] for polling the command.
+    pub fn wait_for(&self) -> crate::process::WaitFor {
+        let mut wait_for = crate::process::WaitFor::new().timeout(self.timeout.unwrap_or(DEFAULT_WAIT_UNTIL_TIMEOUT));
+        if let Some(interval) = self.interval {
+            wait_for = wait_for.interval(interval);
+        }
+        if let Some(backoff) = self.backoff {
+            wait_for = wait_for.backoff(backoff);
+        }
+        if let Some(max_interval) = self.max_interval {
+            wait_for = wait_for.max_interval(max_interval);
+        }
+        if let Some(max_attempts) = self.max_attempts {
+            wait_for = wait_for.max_attempts(max_attempts);
+        }
+        wait_for
+    }
+}
+
+/// Default number of times to retry the [`Verify`] command.
+const DEFAULT_VERIFY_RETRIES: u32 = 3;
+
+/// Verification that a profile is enabled after running the `enable` command.
+///
+/// The command is retried until it succeeds, and enabling the profile fails if it never does.
+///
+/// ```toml
+/// [profiles.envs.linux-nmcli.verify]
+/// # Optional - defaults to the is_enabled command
+/// command = "nmcli -t -f STATE general | grep -q '^connected'"
+/// retries = 5
+/// interval = 2
+/// ```
+#[derive(Clone, Debug, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Verify {
+    /// Command to verify the profile is enabled (defaults to the `is_enabled` command).
+    pub command: Option<CommandString>,
+    /// Number of times to retry the command after the first attempt (defaults to 3).
+    pub retries: Option<u32>,
+    /// Time (in seconds) to sleep for in between attempts (defaults to 1).
+    #[serde(default, deserialize_with = "deserialize_seconds")]
+    pub interval: Option<Duration>,
+}
+
+impl Verify {
+    /// Get the [`crate::process::WaitFor`] for retrying the command.
+    pub fn wait_for(&self) -> crate::process::WaitFor {
+        let mut wait_for =
+            crate::process::WaitFor::new().max_attempts(self.retries.unwrap_or(DEFAULT_VERIFY_RETRIES) + 1);
+        if let Some(interval) = self.interval {
+            wait_for = wait_for.interval(interval);
+        }
+        wait_for
+    }
+}
+
+/// Conditions for detecting whether an environment can be used on the current host.
+///
+/// Every condition that is set must hold. Environments are detected in order of priority
+/// (see: [`Profile::detect_environment`]).
+///
+/// ```toml
+/// [profiles.envs.linux-nmcli]
+/// priority = 10
+/// [profiles.envs.linux-nmcli.detect]
+/// os = "linux"
+/// binary = "nmcli"
+/// # Optional - run without args, with the environment's shell and timeout
+/// command = "systemctl is-active --quiet NetworkManager"
+/// ```
+#[derive(Clone, Debug, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Detect {
+    /// Operating system the host must run (see: [`std::env::consts::OS`], e.g. `linux`, `macos` or `windows`).
+    pub os: Option<String>,
+    /// Executable that must be on the `PATH`.
+    pub binary: Option<String>,
+    /// Path that must exist (`~/` expands to the home directory).
+    pub file: Option<std::path::PathBuf>,
+    /// Command that must succeed.
+    pub command: Option<CommandString>,
+}
+
+impl std::fmt::Display for Detect {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut conditions = Vec::new();
+        if let Some(os) = self.os.as_ref() {
+            conditions.push(format!("os {}", os));
+        }
+        if let Some(binary) = self.binary.as_ref() {
+            conditions.push(format!("binary {}", binary));
+        }
+        if let Some(file) = self.file.as_ref() {
+            conditions.push(format!("file {}", file.display()));
+        }
+        if let Some(command) = self.command.as_ref() {
+            conditions.push(format!("command {}", command.as_str()));
+        }
+        write!(f, "{}", conditions.join(", "))
+    }
+}
+
+/// Check whether an executable is in one of the directories on the `PATH`.
+///
+/// Paths with more than one component are checked as-is. On Windows, `.exe` is appended if there is no extension.
+fn is_on_path(binary: &str) -> bool {
+    let binary = std::path::Path::new(binary);
+    let binary = match binary.extension() {
+        None if cfg!(windows) => binary.with_extension("exe"),
+        _ => binary.to_owned(),
+    };
+    if binary.components().count() > 1 {
+        return binary.is_file();
+    }
+    std::env::var_os("PATH")
+        .is_some_and(|path| std::env::split_paths(&path).any(|directory| directory.join(&binary).is_file()))
+}
+
+impl Detect {
+    /// Check whether every condition holds on the current host.
+    ///
+    /// Commands that cannot be run (e.g. they time out) are treated as failing.
+    pub fn matches(&self, shell: Option<&str>, timeout: Option<Duration>) -> bool {
+        if self.os.as_ref().is_some_and(|os| os != std::env::consts::OS) {
+            return false;
+        }
+        if self.binary.as_ref().is_some_and(|binary| !is_on_path(binary)) {
+            return false;
+        }
+        if self.file.as_ref().is_some_and(|file| !crate::parameter::expand_home(file).exists()) {
+            return false;
+        }
+        self.command.as_ref().is_none_or(|command| {
+            let timeout = command.timeout().or(timeout);
+            match crate::process::run_command_with_options(
+                [command.as_str()],
+                &crate::process::RunOptions { shell, timeout, ..Default::default() },
+            ) {
+                Ok(result) => result.success(),
+                Err(err) => {
+                    log::debug!("Failed to run detect command: {}", err);
+                    false
+                },
+            }
+        })
+    }
+}
+
+/// Environment as written in the config, with every key optional: either a shared template
+/// (in `[environments.<name>]`), or the environment of a profile before the template it extends is applied.
+///
+/// Environments that extend a template only define what differs from it. Their values take precedence,
+/// and their parameters are merged with the template's parameters by name, key by key
+/// (see: [`crate::parameter::ParameterTemplate::merge`]):
+///
+/// ```toml
+/// [environments.linux-nmcli]
+/// can_enable = "nmcli device status | grep -q {device}"
+/// enable = "nmcli device wifi connect {ssid}"
+/// disable = "nmcli radio wifi off"
+/// [environments.linux-nmcli.parameters]
+/// device = "wifi"
+/// ssid = { required = true }
+///
+/// [[profiles]]
+/// name = "home-wifi"
+/// [profiles.envs.linux-nmcli]
+/// extends = "linux-nmcli"
+/// parameters = { ssid = "Home" }
+/// ```
+#[derive(Clone, Debug, Default, serde::Deserialize)]
+pub struct EnvironmentTemplate {
+    /// Name of the template this extends (templates can also extend other templates).
+    pub extends: Option<String>,
+    /// See: [`ProfileEnvironment::shell`].
+    pub shell: Option<String>,
+    /// See: [`ProfileEnvironment::timeout`].
+    #[serde(default, deserialize_with = "deserialize_seconds")]
+    pub timeout: Option<Duration>,
+    /// See: [`ProfileEnvironment::parameters`].
+    pub parameters: Option<HashMap<String, crate::parameter::ParameterTemplate>>,
+    /// See: [`ProfileEnvironment::interpolation`].
+    pub interpolation: Option<Interpolation>,
+    /// See: [`ProfileEnvironment::can_enable`].
+    pub can_enable: Option<CommandString>,
+    /// See: [`ProfileEnvironment::is_enabled`].
+    pub is_enabled: Option<CommandString>,
+    /// See: [`ProfileEnvironment::enable`].
+    pub enable: Option<CommandString>,
+    /// See: [`ProfileEnvironment::disable`].
+    pub disable: Option<CommandString>,
+    /// See: [`ProfileEnvironment::wait_until`].
+    pub wait_until: Option<WaitUntil>,
+    /// See: [`ProfileEnvironment::verify`].
+    pub verify: Option<Verify>,
+    /// See: [`ProfileEnvironment::detect`].
+    pub detect: Option<Detect>,
+    /// See: [`ProfileEnvironment::priority`].
+    pub priority: Option<i32>,
+}
+
+impl EnvironmentTemplate {
+    /// Apply the templates this extends, transitively, so that it no longer extends any template.
+    ///
+    /// # Errors
+    ///
+    /// Returns the reason the templates cannot be applied: a template is not defined, or templates extend each other.
+    pub fn apply(mut self, templates: &HashMap<String, EnvironmentTemplate>) -> Result<Self, String> {
+        let mut path = Vec::new();
+        while let Some(name) = self.extends.take() {
+            if path.contains(&name) {
+                path.push(name);
+                return Err(format!("environment templates extend each other: {}", path.join(" -> ")));
+            }
+            let template = templates.get(&name).ok_or_else(|| format!("environment template {} not found", name))?;
+            self = self.merge(template);
+            path.push(name);
+        }
+        Ok(self)
+    }
+
+    /// Merge a template into this one. Values defined here take precedence, and parameters are merged by name.
+    fn merge(self, template: &EnvironmentTemplate) -> Self {
+        let parameters = match (template.parameters.as_ref(), self.parameters) {
+            (Some(defaults), Some(parameters)) => {
+                let mut merged = defaults.clone();
+                for (name, parameter) in parameters {
+                    let parameter = match defaults.get(&name) {
+                        Some(default) => parameter.merge(default),
+                        None => parameter,
+                    };
+                    merged.insert(name, parameter);
+                }
+                Some(merged)
+            },
+            (defaults, parameters) => parameters.or_else(|| defaults.cloned()),
         };
-        Ok(command)
+        EnvironmentTemplate {
+            extends: template.extends.clone(),
+            shell: self.shell.or_else(|| template.shell.clone()),
+            timeout: self.timeout.or(template.timeout),
+            parameters,
+            interpolation: self.interpolation.or(template.interpolation),
+            can_enable: self.can_enable.or_else(|| template.can_enable.clone()),
+            is_enabled: self.is_enabled.or_else(|| template.is_enabled.clone()),
+            enable: self.enable.or_else(|| template.enable.clone()),
+            disable: self.disable.or_else(|| template.disable.clone()),
+            wait_until: self.wait_until.or_else(|| template.wait_until.clone()),
+            verify: self.verify.or_else(|| template.verify.clone()),
+            detect: self.detect.or_else(|| template.detect.clone()),
+            priority: self.priority.or(template.priority),
+        }
     }
 }
 
@@ -38,13 +597,20 @@ impl CommandString {
 /// using different commands in different environments, like Windows
 /// vs. Linux vs. macOS. Environments (envs) provide the commands
 /// necessary to enable and disable a profile.
-#[derive(Debug, serde::Deserialize)]
+#[derive(Clone, Debug, serde::Deserialize)]
+#[serde(try_from = "EnvironmentTemplate")]
 pub struct ProfileEnvironment {
+    /// Name of the environment template the environment extends, if any (see: [`EnvironmentTemplate`]).
+    pub extends: Option<String>,
     /// Shell to run commands with.
     pub shell: Option<String>,
+    /// Maximum time (in seconds) each command can run for, unless set for the command.
+    pub timeout: Option<Duration>,
     /// Command arguments.
     /// Parameters injected into commands before they're run.
-    pub parameters: Option<HashMap<String, String>>,
+    pub parameters: Option<HashMap<String, crate::parameter::Parameter>>,
+    /// How args are injected into commands.
+    pub interpolation: Option<Interpolation>,
     /// Command to determine whether profile can be enabled.
     pub can_enable: CommandString,
     /// Command to determine whether profile is already enabled.
@@ -53,6 +619,117 @@ pub struct ProfileEnvironment {
     pub enable: CommandString,
     /// Command to disable profile.
     pub disable: CommandString,
+    /// Command to poll after enabling profile until it succeeds.
+    pub wait_until: Option<WaitUntil>,
+    /// Verification that profile is enabled after enabling it.
+    pub verify: Option<Verify>,
+    /// Conditions for detecting whether the environment can be used on the current host.
+    pub detect: Option<Detect>,
+    /// Order in which environments are detected, highest first (defaults to 0).
+    pub priority: i32,
+}
+
+impl TryFrom<EnvironmentTemplate> for ProfileEnvironment {
+    type Error = String;
+
+    /// Convert a template into an environment, once the template it extends (if any) is applied
+    /// (see: [`EnvironmentTemplate::apply`]).
+    fn try_from(template: EnvironmentTemplate) -> Result<Self, Self::Error> {
+        if let Some(extends) = template.extends {
+            return Err(format!("environment templates are not available to extend {}", extends));
+        }
+        let missing =
+            |command: &str| format!("missing command {}, which is required unless a template defines it", command);
+        let parameters = template
+            .parameters
+            .map(|parameters| {
+                parameters
+                    .into_iter()
+                    .map(|(name, parameter)| {
+                        crate::parameter::Parameter::try_from(parameter)
+                            .map(|parameter| (name.to_owned(), parameter))
+                            .map_err(|message| format!("parameter {}: {}", name, message))
+                    })
+                    .collect::<Result<HashMap<_, _>, _>>()
+            })
+            .transpose()?;
+        Ok(ProfileEnvironment {
+            extends: None,
+            shell: template.shell,
+            timeout: template.timeout,
+            parameters,
+            interpolation: template.interpolation,
+            can_enable: template.can_enable.ok_or_else(|| missing("can_enable"))?,
+            is_enabled: template.is_enabled,
+            enable: template.enable.ok_or_else(|| missing("enable"))?,
+            disable: template.disable.ok_or_else(|| missing("disable"))?,
+            wait_until: template.wait_until,
+            verify: template.verify,
+            detect: template.detect,
+            priority: template.priority.unwrap_or_default(),
+        })
+    }
+}
+
+impl ProfileEnvironment {
+    /// Get the shell to quote args for, or `None` if args are injected as-is.
+    ///
+    /// The environment interpolation takes precedence over the interpolation in the [`ExecutionOptions`].
+    fn quoting(&self, options: &ExecutionOptions) -> Option<crate::escape::Shell> {
+        match self.interpolation.or(options.interpolation).unwrap_or_default() {
+            Interpolation::Quoted => {
+                Some(crate::escape::Shell::from_path(self.shell.as_deref().unwrap_or(crate::process::DEFAULT_SHELL)))
+            },
+            Interpolation::Raw => None,
+        }
+    }
+
+    /// Replace the args of secret parameters with [`REDACTED`].
+    ///
+    /// Commands rendered with the redacted args can be logged and shown,
+    /// as secrets are masked however the args are quoted or filtered.
+    fn redact_args<'a>(&self, args: Option<&HashMap<&'a str, &'a str>>) -> Option<HashMap<&'a str, &'a str>> {
+        args.map(|args| {
+            args.iter()
+                .map(|(name, value)| {
+                    let is_secret = self
+                        .parameters
+                        .as_ref()
+                        .and_then(|parameters| parameters.get(*name))
+                        .is_some_and(crate::parameter::Parameter::is_secret);
+                    (*name, if is_secret { REDACTED } else { *value })
+                })
+                .collect()
+        })
+    }
+
+    /// Get a [`Redactor`] that masks the args of secret parameters, e.g. in command output.
+    fn redactor(&self, args: Option<&HashMap<&str, &str>>) -> Redactor {
+        Redactor::new(args.into_iter().flatten().filter_map(|(name, value)| {
+            self.parameters
+                .as_ref()
+                .and_then(|parameters| parameters.get(*name))
+                .filter(|parameter| parameter.is_secret())
+                .map(|_| *value)
+        }))
+    }
+
+    /// Get the command used to verify the profile is enabled, if verification is configured.
+    ///
+    /// Falls back to the `is_enabled` command if the verification does not define a command.
+    pub fn verify_command(&self) -> Option<&CommandString> {
+        self.verify.as_ref().and_then(|verify| verify.command.as_ref().or(self.is_enabled.as_ref()))
+    }
+
+    /// Get the environment's commands paired with their names.
+    pub fn commands(&self) -> impl Iterator<Item = (&'static str, &CommandString)> {
+        [("can_enable", Some(&self.can_enable)), ("is_enabled", self.is_enabled.as_ref())]
+            .into_iter()
+            .chain([("enable", Some(&self.enable)), ("disable", Some(&self.disable))])
+            .chain([("wait_until", self.wait_until.as_ref().map(|wait_until| &wait_until.command))])
+            .chain([("verify", self.verify.as_ref().and_then(|verify| verify.command.as_ref()))])
+            .filter_map(|(name, command)| command.map(|command| (name, command)))
+    }
 }
 
 /// Dependency profile.
@@ -60,7 +737,7 @@ pub struct ProfileEnvironment {
 /// Profiles may depend on one or more other profiles,
 /// and may optionally specify the environment they depend on
 /// (if a different environment is required).
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct Dependency {
     pub name: String,
     pub env_name: Option<String>,
@@ -79,6 +756,15 @@ impl<'de> serde::Deserialize<'de> for Dependency {
     }
 }
 
+impl std::fmt::Display for Dependency {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.env_name.as_ref() {
+            Some(env_name) => write!(f, "{}:{}", self.name, env_name),
+            None => write!(f, "{}", self.name),
+        }
+    }
+}
+
 /// Profile.
 ///
 /// Profiles are metadata and instructions for configuring networking on a host.
@@ -107,22 +793,34 @@ impl<'de> serde::Deserialize<'de> for Dependency {
 /// test "connected" = $STATUS"""
 /// enable = """
 /// nmcli radio {device} on
-/// ! test -z "{ssid}" && nmcli dev {device} connect {ssid}"""
+/// ! test -z {ssid} && nmcli dev {device} connect {ssid}"""
 /// disable = """
 /// nmcli radio {device} off"""
 /// ```
 #[derive(Debug, serde::Deserialize)]
 pub struct Profile {
+    /// Config file the profile was loaded from, if it was loaded with [`ProfileConfig::load`].
+    #[serde(skip)]
+    pub source: Option<std::path::PathBuf>,
     /// Name of the profile.
     pub name: String,
     /// Profile name aliases.
     pub aliases: Option<Vec<String>>,
+    /// Name or alias of the profile this profile extends, if any.
+    ///
+    /// Profiles inherit the environments and dependencies of the profile they extend (but not its aliases),
+    /// and can only override the defaults of its parameters. Inheritance is resolved when a [`ProfileConfig`]
+    /// is loaded.
+    pub extends: Option<String>,
     /// Profile dependencies.
     /// Refers to one or more profile names or aliases.
     pub dependencies: Option<Vec<Dependency>>,
     /// Profile environments.
     /// Dependency-only environments do not define any envs.
     pub envs: Option<HashMap<String, ProfileEnvironment>>,
+    /// Environment detected on the current host, once detected (see: [`Profile::detect_environment`]).
+    #[serde(skip)]
+    detected_environment: std::sync::OnceLock<Option<String>>,
 }
 
 impl Profile {
@@ -143,22 +841,128 @@ impl Profile {
     /// * if no arguments are provided, then `device = "wifi"` and `ssid = "SomeSSID"`.
     /// * if `device` is set to `"radio2"`, then `device = "radio2"` and `ssid = "SomeSSID"`.
     ///
-    /// Default parameter values can also be empty `""`.
-    fn transform_args<'a: 'b, 'b>(
+    /// Default parameter values can also be empty `""`, and optional parameters without a default value are empty.
+    /// Parameters with a [`crate::parameter::SecretSource`] and no arg use the given secrets instead of a default value,
+    /// and are left out if no secrets are given, so they are only resolved for the commands that use them
+    /// (see: [`Profile::resolve_secrets`]).
+    ///
+    /// # Errors
+    ///
+    /// [`crate::error::Error::InvalidArgs`]: If a required arg is missing, or an arg does not match its parameter.
+    fn transform_args<'a>(
         &'a self,
         environment: &'a ProfileEnvironment,
         args: Option<&'a HashMap<String, String>>,
-    ) -> Option<HashMap<&'b str, interpolator::Formattable<'b>>> {
-        environment.parameters.as_ref().map(move |parameters| {
+        secrets: Option<&'a HashMap<&str, String>>,
+    ) -> crate::error::Result<Option<HashMap<&'a str, &'a str>>> {
+        let issues = self.check_args(environment, args);
+        if !issues.is_empty() {
+            return Err(crate::error::Error::InvalidArgs { issues });
+        }
+        Ok(environment.parameters.as_ref().map(move |parameters| {
             parameters
                 .iter()
-                .map(|(k, v)| {
-                    args.as_ref()
-                        .map(|args| (k.as_str(), interpolator::Formattable::display(args.get(k.as_str()).unwrap_or(v))))
-                        .unwrap_or_else(|| (k.as_str(), interpolator::Formattable::display(v)))
+                .filter_map(|(name, parameter)| {
+                    let value = match args.and_then(|args| args.get(name.as_str())) {
+                        Some(value) => Some(value),
+                        None if parameter.source.is_some() => Some(secrets?.get(name.as_str())?),
+                        None => parameter.default.as_ref(),
+                    };
+                    Some((name.as_str(), value.map_or("", String::as_str)))
                 })
                 .collect()
-        })
+        }))
+    }
+
+    /// Check args against the parameters of the given [`ProfileEnvironment`].
+    ///
+    /// Args for parameters the environment does not define are ignored, as args can be shared by several profiles
+    /// (see: [`crate::validation::check_unknown_args`]).
+    pub(crate) fn check_args(
+        &self,
+        environment: &ProfileEnvironment,
+        args: Option<&HashMap<String, String>>,
+    ) -> Vec<crate::validation::ArgumentIssue> {
+        let mut parameters = environment.parameters.iter().flatten().collect::<Vec<_>>();
+        parameters.sort_by_key(|(name, _)| name.as_str());
+        parameters
+            .into_iter()
+            .filter_map(|(name, parameter)| match args.and_then(|args| args.get(name.as_str())) {
+                Some(value) => parameter.check(value).map(|reason| crate::validation::ArgumentIssue::Invalid {
+                    profile: self.name.to_owned(),
+                    name: name.to_owned(),
+                    value: if parameter.is_secret() { REDACTED.to_owned() } else { value.to_owned() },
+                    reason,
+                }),
+                None if parameter.required => Some(crate::validation::ArgumentIssue::Missing {
+                    profile: self.name.to_owned(),
+                    name: name.to_owned(),
+                }),
+                None => None,
+            })
+            .collect()
+    }
+
+    /// Resolve the secrets of parameters of the given [`ProfileEnvironment`] that the command uses and that read
+    /// their values from a [`crate::parameter::SecretSource`], by parameter name.
+    ///
+    /// Secrets are only resolved for parameters without an arg, so a command that does not use a secret never reads it.
+    /// Secrets are read from the cache in the [`ExecutionOptions`] if set, so that each is only resolved once.
+    /// Commands are run with the environment's shell and timeout, and their output is never logged.
+    ///
+    /// # Errors
+    ///
+    /// [`crate::error::Error::SecretUnavailable`]: If a secret cannot be read (including if its command does not exit
+    /// before the timeout), or does not match its parameter (the secret is masked in the error).
+    fn resolve_secrets<'a>(
+        environment: &'a ProfileEnvironment,
+        command: &CommandString,
+        args: Option<&HashMap<&str, &str>>,
+        options: &ExecutionOptions,
+    ) -> crate::error::Result<HashMap<&'a str, String>> {
+        let Some(parameters) = environment.parameters.as_ref() else {
+            return Ok(HashMap::new());
+        };
+        command
+            .placeholders()
+            .into_iter()
+            .filter(|name| args.is_none_or(|args| !args.contains_key(name)))
+            .filter_map(|name| parameters.get_key_value(name))
+            .filter_map(|(name, parameter)| parameter.source.as_ref().map(|source| (name.as_str(), parameter, source)))
+            .map(|(name, parameter, source)| {
+                let (shell, timeout) = (environment.shell.as_deref(), environment.timeout.or(options.timeout));
+                let secret = match options.secrets.as_ref() {
+                    Some(secrets) => secrets.resolve(source, name, shell, timeout),
+                    None => source.resolve(name, shell, timeout),
+                }
+                .map_err(|err| match err {
+                    crate::error::Error::Timeout { after, .. } => crate::error::Error::SecretUnavailable {
+                        parameter: name.to_owned(),
+                        message: format!("command timed out after {:?} ({})", after, source),
+                    },
+                    err => err,
+                })?;
+                if let Some(reason) = parameter.check(&secret) {
+                    return Err(crate::error::Error::SecretUnavailable {
+                        parameter: name.to_owned(),
+                        message: format!("invalid value {:?} from {}, {}", REDACTED, source, reason),
+                    });
+                }
+                Ok((name, secret))
+            })
+            .collect()
+    }
+
+    /// Secrets of parameters of the given [`ProfileEnvironment`] that read their values from
+    /// a [`crate::parameter::SecretSource`], replaced with [`REDACTED`] without resolving them.
+    fn redacted_secrets(environment: &ProfileEnvironment) -> HashMap<&str, String> {
+        environment
+            .parameters
+            .iter()
+            .flatten()
+            .filter(|(_, parameter)| parameter.source.is_some())
+            .map(|(name, _)| (name.as_str(), REDACTED.to_owned()))
+            .collect()
     }
 
     /// Get [`ProfileEnvironment`] by name.
@@ -166,7 +970,10 @@ impl Profile {
     /// # Errors
     ///
     /// [`crate::error::Error::InvalidEnvironment`]: If the environment is not defined for the profile.
-    fn get_environment<S: AsRef<str>>(&self, environment_name: S) -> crate::error::Result<&ProfileEnvironment> {
+    pub(crate) fn get_environment<S: AsRef<str>>(
+        &self,
+        environment_name: S,
+    ) -> crate::error::Result<&ProfileEnvironment> {
         let env = match self.envs.as_ref() {
             Some(envs) => envs.get(environment_name.as_ref()),
             None => None,
@@ -178,25 +985,113 @@ impl Profile {
         })
     }
 
-    /// Run the `can_enable` command using the given [`ProfileEnvironment`].
+    /// Inject args into a command and run it using the given [`ProfileEnvironment`].
+    ///
+    /// The command timeout takes precedence over the environment timeout, which takes precedence over
+    /// the timeout in the [`ExecutionOptions`]. Interactive `enable` and `disable` commands are run with
+    /// the terminal's stdio, so their output is not captured.
+    /// Args are also passed to the command as environment variables (see: [`parameter_variable`]).
+    /// Secrets the command uses are resolved first (see: [`Profile::resolve_secrets`]), and only those are passed
+    /// to the command.
+    ///
+    /// Args of secret parameters are masked in the logged and recorded command, and in the command output
+    /// (including the output returned, so that errors built from it do not include secrets).
     ///
     /// # Errors
     ///
-    /// [`crate::error::Error::ProfileRequirementsNotMet`]: If the profile cannot be enabled for the environment.
-    fn _can_enable(
+    /// [`crate::error::Error::SecretUnavailable`]: If a secret the command uses cannot be resolved.
+    /// [`crate::error::Error::Timeout`]: If the command does not exit before the timeout.
+    /// [`crate::error::Error::Io`]: If any IO errors occur when attempting to running the command.
+    fn run_command(
         &self,
+        environment_name: &str,
         environment: &ProfileEnvironment,
-        args: Option<&HashMap<&str, interpolator::Formattable<'_>>>,
-    ) -> crate::error::Result<()> {
-        let command = environment.can_enable.prepare_with_args(args)?;
-        log::debug!("Running command can_enable: {}", &command);
-        let result = crate::process::run_command([command], environment.shell.as_deref())
-            .map_err(|err| crate::error::Error::ProfileRequirementsNotMet { message: err.to_string() })?;
+        phase: CommandPhase,
+        command: &CommandString,
+        args: Option<&HashMap<&str, &str>>,
+        options: &ExecutionOptions,
+    ) -> crate::error::Result<crate::process::CommandResult> {
+        let secrets = Self::resolve_secrets(environment, command, args, options)?;
+        let args = args.map(|args| {
+            let mut args = args.clone();
+            args.extend(secrets.iter().map(|(name, secret)| (*name, secret.as_str())));
+            args
+        });
+        let args = args.as_ref();
+        let timeout = command.timeout().or(environment.timeout).or(options.timeout);
+        let interactive = command.interactive() && matches!(phase, CommandPhase::Enable | CommandPhase::Disable);
+        let quoting = environment.quoting(options);
+        let redactor = environment.redactor(args);
+        let redacted_command = command.prepare_with_args(environment.redact_args(args).as_ref(), quoting)?;
+        log::debug!("Running command {}: {}", phase, redacted_command);
+        let command = command.prepare_with_args(args, quoting)?;
+        let variables = args
+            .into_iter()
+            .flatten()
+            .map(|(name, value)| (parameter_variable(name), (*value).to_owned()))
+            .collect::<Vec<_>>();
+        let started = std::time::Instant::now();
+        let result = crate::process::run_command_with_options(
+            [command.as_str()],
+            &crate::process::RunOptions {
+                shell: environment.shell.as_deref(),
+                timeout,
+                on_output: options.on_output.as_ref().map(|on_output| on_output.redacted(redactor.clone())),
+                interactive,
+                env: &variables,
+            },
+        )
+        .map(|result| result.redact(&redactor))
+        .map_err(|err| match err {
+            crate::error::Error::Timeout { after, .. } => {
+                crate::error::Error::Timeout { command: redacted_command.clone(), after }
+            },
+            err => err,
+        });
+        if let Some(recorder) = options.recorder.as_ref() {
+            let output = |output: crate::error::Result<Cow<'_, str>>| output.map(Cow::into_owned).unwrap_or_default();
+            recorder.record(crate::report::CommandRecord {
+                profile: self.name.to_owned(),
+                environment: environment_name.to_owned(),
+                phase,
+                command: redacted_command,
+                exit_code: result.as_ref().ok().and_then(|result| result.code()),
+                stdout: result.as_ref().map(|result| output(result.stdout())).unwrap_or_default(),
+                stderr: result.as_ref().map(|result| output(result.stderr())).unwrap_or_default(),
+                duration: started.elapsed(),
+                error: result.as_ref().err().map(ToString::to_string),
+            });
+        }
+        let result = result?;
         if log::log_enabled!(log::Level::Debug) {
             if let Some(code) = result.code().as_ref() {
                 log::debug!("Command exited with code {}", code);
             }
         }
+        Ok(result)
+    }
+
+    /// Run the `can_enable` command using the given [`ProfileEnvironment`].
+    ///
+    /// # Errors
+    ///
+    /// [`crate::error::Error::ProfileRequirementsNotMet`]: If the profile cannot be enabled for the environment.
+    /// [`crate::error::Error::Timeout`]: If the command does not exit before the timeout.
+    fn _can_enable(
+        &self,
+        environment_name: &str,
+        environment: &ProfileEnvironment,
+        args: Option<&HashMap<&str, &str>>,
+        options: &ExecutionOptions,
+    ) -> crate::error::Result<()> {
+        let result = self
+            .run_command(environment_name, environment, CommandPhase::CanEnable, &environment.can_enable, args, options)
+            .map_err(|err| match err {
+                crate::error::Error::Io(err) => {
+                    crate::error::Error::ProfileRequirementsNotMet { message: err.to_string() }
+                },
+                err => err,
+            })?;
         if !result.success() {
             Err(crate::error::Error::ProfileRequirementsNotMet {
                 message: result
@@ -213,24 +1108,21 @@ impl Profile {
     ///
     /// # Errors
     ///
+    /// [`crate::error::Error::Timeout`]: If the command does not exit before the timeout.
     /// [`crate::error::Error::Io`]: If any IO errors occur when attempting to running the command.
     fn _is_enabled(
         &self,
+        environment_name: &str,
         environment: &ProfileEnvironment,
-        args: Option<&HashMap<&str, interpolator::Formattable<'_>>>,
-    ) -> crate::error::Result<bool> {
+        args: Option<&HashMap<&str, &str>>,
+        options: &ExecutionOptions,
+    ) -> crate::error::Result<ProfileStatus> {
         if let Some(is_enabled) = environment.is_enabled.as_ref() {
-            let command = is_enabled.prepare_with_args(args)?;
-            log::debug!("Running command is_enabled: {}", &command);
-            let result = crate::process::run_command([command], environment.shell.as_deref())?;
-            if log::log_enabled!(log::Level::Debug) {
-                if let Some(code) = result.code().as_ref() {
-                    log::debug!("Command exited with code {}", code);
-                }
-            }
-            Ok(result.success())
+            let result =
+                self.run_command(environment_name, environment, CommandPhase::IsEnabled, is_enabled, args, options)?;
+            Ok(if result.success() { ProfileStatus::Enabled } else { ProfileStatus::Disabled })
         } else {
-            Ok(false)
+            Ok(ProfileStatus::Unknown)
         }
     }
 
@@ -239,20 +1131,17 @@ impl Profile {
     /// # Errors
     ///
     /// [`crate::error::Error::CommandFailure`]: If the command has a non-zero exit code.
+    /// [`crate::error::Error::Timeout`]: If the command does not exit before the timeout.
     /// [`crate::error::Error::Io`]: If any IO errors occur when attempting to running the command.
     fn _enable(
         &self,
+        environment_name: &str,
         environment: &ProfileEnvironment,
-        args: Option<&HashMap<&str, interpolator::Formattable<'_>>>,
+        args: Option<&HashMap<&str, &str>>,
+        options: &ExecutionOptions,
     ) -> crate::error::Result<()> {
-        let command = environment.enable.prepare_with_args(args)?;
-        log::debug!("Running command enable: {}", &command);
-        let result = crate::process::run_command([command], environment.shell.as_deref())?;
-        if log::log_enabled!(log::Level::Debug) {
-            if let Some(code) = result.code().as_ref() {
-                log::debug!("Command exited with code {}", code);
-            }
-        }
+        let result =
+            self.run_command(environment_name, environment, CommandPhase::Enable, &environment.enable, args, options)?;
         if !result.success() {
             Err(crate::error::Error::CommandFailure {
                 code: result.code().unwrap_or(-1),
@@ -271,20 +1160,23 @@ impl Profile {
     /// # Errors
     ///
     /// [`crate::error::Error::CommandFailure`]: If the command has a non-zero exit code.
+    /// [`crate::error::Error::Timeout`]: If the command does not exit before the timeout.
     /// [`crate::error::Error::Io`]: If any IO errors occur when attempting to running the command.
     fn _disable(
         &self,
+        environment_name: &str,
         environment: &ProfileEnvironment,
-        args: Option<&HashMap<&str, interpolator::Formattable<'_>>>,
+        args: Option<&HashMap<&str, &str>>,
+        options: &ExecutionOptions,
     ) -> crate::error::Result<()> {
-        let command = environment.disable.prepare_with_args(args)?;
-        log::debug!("Running command disable: {}", &command);
-        let result = crate::process::run_command([command], environment.shell.as_deref())?;
-        if log::log_enabled!(log::Level::Debug) {
-            if let Some(code) = result.code().as_ref() {
-                log::debug!("Command exited with code {}", code);
-            }
-        }
+        let result = self.run_command(
+            environment_name,
+            environment,
+            CommandPhase::Disable,
+            &environment.disable,
+            args,
+            options,
+        )?;
         if !result.success() {
             Err(crate::error::Error::CommandFailure {
                 code: result.code().unwrap_or(-1),
@@ -298,81 +1190,1108 @@ impl Profile {
         }
     }
 
-    /// Check whether the profile is a Composition Profile.
-    pub fn is_composition_profile(&self) -> bool {
-        self.envs.is_none() && !self.dependencies.is_none()
+    /// Poll the `wait_until` command using the given [`ProfileEnvironment`] until it succeeds, if defined.
+    ///
+    /// # Errors
+    ///
+    /// [`crate::error::Error::WaitTimeout`]: If the command does not succeed before the deadline or maximum attempts.
+    /// [`crate::error::Error::Timeout`]: If an attempt does not exit before the command timeout.
+    /// [`crate::error::Error::Io`]: If any IO errors occur when attempting to running the command.
+    fn _wait_until(
+        &self,
+        environment_name: &str,
+        environment: &ProfileEnvironment,
+        args: Option<&HashMap<&str, &str>>,
+        options: &ExecutionOptions,
+    ) -> crate::error::Result<()> {
+        if let Some(wait_until) = environment.wait_until.as_ref() {
+            wait_until.wait_for().run(|| {
+                let result = self.run_command(
+                    environment_name,
+                    environment,
+                    CommandPhase::WaitUntil,
+                    &wait_until.command,
+                    args,
+                    options,
+                )?;
+                Ok(result.success())
+            })?;
+        }
+        Ok(())
     }
 
-    /// Validate the profile.
+    /// Retry the `verify` command using the given [`ProfileEnvironment`] until it succeeds, if verification is configured.
     ///
-    /// # Rules
+    /// # Errors
     ///
-    /// 1. Only composition profiles, which compose one or more profiles with no additional logic, can leave `envs` empty.
-    pub fn is_valid(&self) -> crate::error::Result<()> {
+    /// [`crate::error::Error::VerificationFailed`]: If the command does not succeed within the number of retries
+    /// (attempts that do not exit before the command timeout count as failed attempts).
+    /// [`crate::error::Error::Io`]: If any IO errors occur when attempting to running the command.
+    fn _verify(
+        &self,
+        environment_name: &str,
+        environment: &ProfileEnvironment,
+        args: Option<&HashMap<&str, &str>>,
+        options: &ExecutionOptions,
+    ) -> crate::error::Result<()> {
+        if let (Some(verify), Some(command)) = (environment.verify.as_ref(), environment.verify_command()) {
+            verify
+                .wait_for()
+                .run(|| {
+                    match self.run_command(environment_name, environment, CommandPhase::Verify, command, args, options)
+                    {
+                        Ok(result) => Ok(result.success()),
+                        // The profile is not enabled yet, so the attempt is retried
+                        Err(
+                            err @ (crate::error::Error::Timeout { .. } | crate::error::Error::CommandFailure { .. }),
+                        ) => {
+                            log::debug!("Verification attempt failed: {}", err);
+                            Ok(false)
+                        },
+                        Err(err) => Err(err),
+                    }
+                })
+                .map_err(|err| match err {
+                    crate::error::Error::WaitTimeout { attempts, .. } => {
+                        crate::error::Error::VerificationFailed { profile: self.name.to_owned(), attempts }
+                    },
+                    err => err,
+                })?;
+        }
+        Ok(())
+    }
+
+    /// Check whether the profile is a Composition Profile.
+    pub fn is_composition_profile(&self) -> bool {
+        self.envs.is_none() && self.dependencies.is_some()
+    }
+
+    /// Detect the environment to use on the current host.
+    ///
+    /// Environments that define [`ProfileEnvironment::detect`] conditions are checked in order of priority
+    /// (highest first, then by name), and the first environment whose conditions hold is returned.
+    /// The detected environment is cached, so `detect` commands run at most once per profile.
+    pub fn detect_environment(&self) -> Option<&str> {
+        self.detected_environment.get_or_init(|| self.detect_environment_uncached()).as_deref()
+    }
+
+    fn detect_environment_uncached(&self) -> Option<String> {
+        let mut environments = self
+            .envs
+            .iter()
+            .flatten()
+            .filter_map(|(name, environment)| environment.detect.as_ref().map(|detect| (name, environment, detect)))
+            .collect::<Vec<_>>();
+        environments.sort_by(|(a_name, a, _), (b_name, b, _)| b.priority.cmp(&a.priority).then(a_name.cmp(b_name)));
+        let detected = environments
+            .into_iter()
+            .find(|(_, environment, detect)| detect.matches(environment.shell.as_deref(), environment.timeout))
+            .map(|(name, _, _)| name.to_owned());
+        if let Some(name) = detected.as_ref() {
+            log::debug!("Detected environment {} for profile {}", name, self.name);
+        }
+        detected
+    }
+
+    /// Choose the environment to use: the given environment if any, otherwise the detected environment
+    /// (see: [`Profile::detect_environment`]), otherwise the environment named after the current operating system
+    /// (see: [`std::env::consts::OS`], e.g. `linux`, `macos` or `windows`).
+    pub fn choose_environment<'a>(&'a self, environment_name: Option<&'a str>) -> &'a str {
+        environment_name.or_else(|| self.detect_environment()).unwrap_or(std::env::consts::OS)
+    }
+
+    /// Validate the profile, independent of the other profiles in the config.
+    ///
+    /// # Rules
+    ///
+    /// 1. Only composition profiles, which compose one or more profiles with no additional logic, can leave `envs` empty.
+    /// 2. Every parameter should be used by at least one of the environment's commands (warning).
+    /// 3. Environments that verify the profile define a `verify` or `is_enabled` command.
+    /// 4. Commands can only use parameters declared by the environment, and filters that exist. Commands of
+    ///    environments without parameters are not interpolated, so placeholders in them are reported (warning).
+    /// 5. Only `enable` and `disable` commands should be interactive, as the output of others is needed (warning).
+    /// 6. Parameter schemas are consistent, e.g. default values match the parameter type.
+    pub fn validate(&self) -> Vec<crate::validation::ValidationIssueKind> {
+        let mut issues = Vec::new();
         if self.envs.is_none() && self.dependencies.is_none() {
-            return Err(crate::error::Error::InvalidProfile {
-                profile: self.name.to_string(),
-                message: "One or more environments must be defined".to_string(),
-            });
+            issues.push(crate::validation::ValidationIssueKind::MissingEnvironments);
         }
 
-        Ok(())
+        let mut envs = self.envs.iter().flatten().collect::<Vec<_>>();
+        envs.sort_by_key(|(env_name, _)| env_name.as_str());
+        for (env_name, environment) in envs {
+            if environment.verify.is_some() && environment.verify_command().is_none() {
+                issues.push(crate::validation::ValidationIssueKind::MissingVerifyCommand {
+                    environment: env_name.to_owned(),
+                });
+            }
+            for (command_name, command) in environment.commands() {
+                if command.interactive() && !matches!(command_name, "enable" | "disable") {
+                    issues.push(crate::validation::ValidationIssueKind::IgnoredInteractive {
+                        environment: env_name.to_owned(),
+                        command: command_name.to_owned(),
+                    });
+                }
+            }
+
+            let Some(parameters) = environment.parameters.as_ref() else {
+                // Other brackets, e.g. `awk '{ print $2 }'`, are left as-is as they are not interpolated either
+                for (command_name, command) in environment.commands() {
+                    let placeholders = command.placeholders().into_iter().filter(|name| {
+                        !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-'))
+                    });
+                    for placeholder in placeholders {
+                        issues.push(crate::validation::ValidationIssueKind::UninterpolatedPlaceholder {
+                            environment: env_name.to_owned(),
+                            command: command_name.to_owned(),
+                            parameter: placeholder.to_owned(),
+                        });
+                    }
+                }
+                continue;
+            };
+
+            let mut names = parameters.keys().collect::<Vec<_>>();
+            names.sort();
+            for name in names {
+                if let Some(reason) = parameters[name].check_schema() {
+                    issues.push(crate::validation::ValidationIssueKind::InvalidParameter {
+                        environment: env_name.to_owned(),
+                        parameter: name.to_owned(),
+                        reason,
+                    });
+                }
+            }
+
+            let mut used = HashSet::new();
+            for (command_name, command) in environment.commands() {
+                for placeholder in command.placeholders() {
+                    if !parameters.contains_key(placeholder) {
+                        issues.push(crate::validation::ValidationIssueKind::UndeclaredParameter {
+                            environment: env_name.to_owned(),
+                            command: command_name.to_owned(),
+                            parameter: placeholder.to_owned(),
+                        });
+                    }
+                    used.insert(placeholder);
+                }
+                for filter in command.filters() {
+                    if filter.parse::<crate::escape::Filter>().is_err() {
+                        issues.push(crate::validation::ValidationIssueKind::UnknownFilter {
+                            environment: env_name.to_owned(),
+                            command: command_name.to_owned(),
+                            filter: filter.to_owned(),
+                        });
+                    }
+                }
+            }
+
+            let mut unused =
+                parameters.keys().filter(|parameter| !used.contains(parameter.as_str())).collect::<Vec<_>>();
+            unused.sort();
+            for parameter in unused {
+                issues.push(crate::validation::ValidationIssueKind::UnusedParameter {
+                    environment: env_name.to_owned(),
+                    parameter: parameter.to_owned(),
+                });
+            }
+        }
+
+        issues
+    }
+
+    /// Check whether the profile is valid, independent of the other profiles in the config (see: [`Profile::validate`]).
+    ///
+    /// Warnings do not make the profile invalid.
+    ///
+    /// # Errors
+    ///
+    /// [`crate::error::Error::InvalidProfile`]: If any rule is broken, with every error-severity issue in the message.
+    pub fn is_valid(&self) -> crate::error::Result<()> {
+        let errors = self
+            .validate()
+            .into_iter()
+            .filter(|issue| issue.severity() == crate::validation::Severity::Error)
+            .map(|issue| issue.to_string())
+            .collect::<Vec<_>>();
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(crate::error::Error::InvalidProfile { profile: self.name.to_owned(), message: errors.join("; ") })
+        }
+    }
+
+    /// Prepare a command from the given [`ProfileEnvironment`] without running it.
+    ///
+    /// Args of secret parameters are masked in the rendered command, as prepared commands are shown to users.
+    fn prepare(
+        &self,
+        environment: &ProfileEnvironment,
+        phase: CommandPhase,
+        command: &CommandString,
+        args: Option<&HashMap<&str, &str>>,
+        options: &ExecutionOptions,
+    ) -> crate::error::Result<PreparedCommand> {
+        Ok(PreparedCommand {
+            phase,
+            command: command.prepare_with_args(environment.redact_args(args).as_ref(), environment.quoting(options))?,
+            shell: environment.shell.as_deref().unwrap_or(crate::process::DEFAULT_SHELL).to_owned(),
+        })
+    }
+
+    /// Prepare the commands that [`Profile::enable`] would run, in order, without running them.
+    ///
+    /// The `enable` command only runs if the `is_enabled` command (when defined) fails,
+    /// and is followed by polling the `wait_until` command and retrying the `verify` command (when defined).
+    ///
+    /// # Errors
+    ///
+    /// [`crate::error::Error::InvalidEnvironment`]: If the environment is not defined for the profile.
+    /// [`crate::error::Error::Format`]: If args cannot be injected into a command.
+    pub fn prepare_enable<S>(
+        &self,
+        environment_name: S,
+        args: Option<&HashMap<String, String>>,
+        options: &ExecutionOptions,
+    ) -> crate::error::Result<Vec<PreparedCommand>>
+    where
+        S: AsRef<str>,
+    {
+        let environment = self.get_environment(environment_name)?;
+        let secrets = Self::redacted_secrets(environment);
+        let environment_args = self.transform_args(environment, args, Some(&secrets))?;
+        let mut commands = vec![self.prepare(
+            environment,
+            CommandPhase::CanEnable,
+            &environment.can_enable,
+            environment_args.as_ref(),
+            options,
+        )?];
+        if let Some(is_enabled) = environment.is_enabled.as_ref() {
+            commands.push(self.prepare(
+                environment,
+                CommandPhase::IsEnabled,
+                is_enabled,
+                environment_args.as_ref(),
+                options,
+            )?);
+        }
+        commands.push(self.prepare(
+            environment,
+            CommandPhase::Enable,
+            &environment.enable,
+            environment_args.as_ref(),
+            options,
+        )?);
+        if let Some(wait_until) = environment.wait_until.as_ref() {
+            commands.push(self.prepare(
+                environment,
+                CommandPhase::WaitUntil,
+                &wait_until.command,
+                environment_args.as_ref(),
+                options,
+            )?);
+        }
+        if let Some(verify) = environment.verify_command() {
+            commands.push(self.prepare(
+                environment,
+                CommandPhase::Verify,
+                verify,
+                environment_args.as_ref(),
+                options,
+            )?);
+        }
+        Ok(commands)
+    }
+
+    /// Prepare the commands that [`Profile::disable`] would run, in order, without running them.
+    ///
+    /// The `disable` command only runs if the `is_enabled` command succeeds,
+    /// so nothing is run for environments that do not define `is_enabled`.
+    ///
+    /// # Errors
+    ///
+    /// [`crate::error::Error::InvalidEnvironment`]: If the environment is not defined for the profile.
+    /// [`crate::error::Error::Format`]: If args cannot be injected into a command.
+    pub fn prepare_disable<S>(
+        &self,
+        environment_name: S,
+        args: Option<&HashMap<String, String>>,
+        options: &ExecutionOptions,
+    ) -> crate::error::Result<Vec<PreparedCommand>>
+    where
+        S: AsRef<str>,
+    {
+        let environment = self.get_environment(environment_name)?;
+        let secrets = Self::redacted_secrets(environment);
+        let environment_args = self.transform_args(environment, args, Some(&secrets))?;
+        let mut commands = Vec::with_capacity(2);
+        if let Some(is_enabled) = environment.is_enabled.as_ref() {
+            commands.push(self.prepare(
+                environment,
+                CommandPhase::IsEnabled,
+                is_enabled,
+                environment_args.as_ref(),
+                options,
+            )?);
+            commands.push(self.prepare(
+                environment,
+                CommandPhase::Disable,
+                &environment.disable,
+                environment_args.as_ref(),
+                options,
+            )?);
+        }
+        Ok(commands)
+    }
+
+    /// Check args against the parameters of the given environment, without running any commands.
+    ///
+    /// Returns every missing required arg, every arg that does not match its parameter,
+    /// and every arg that is not a parameter of the environment.
+    ///
+    /// # Errors
+    ///
+    /// [`crate::error::Error::InvalidEnvironment`]: If the environment is not defined for the profile.
+    pub fn validate_args<S>(
+        &self,
+        environment_name: S,
+        args: Option<&HashMap<String, String>>,
+    ) -> crate::error::Result<Vec<crate::validation::ArgumentIssue>>
+    where
+        S: AsRef<str>,
+    {
+        let environment = self.get_environment(environment_name)?;
+        let mut issues = self.check_args(environment, args);
+        issues.extend(crate::validation::check_unknown_args(
+            args,
+            environment.parameters.iter().flatten().map(|(name, _)| name.as_str()),
+        ));
+        Ok(issues)
+    }
+
+    /// Check whether the profile is enabled using the given environment.
+    ///
+    /// Returns [`ProfileStatus::Unknown`] if the environment does not define an `is_enabled` command.
+    ///
+    /// # Errors
+    ///
+    /// [`crate::error::Error::InvalidEnvironment`]: If the environment is not defined for the profile.
+    /// [`crate::error::Error::Timeout`]: If the command does not exit before the timeout.
+    /// [`crate::error::Error::Io`]: If any IO errors occur when attempting to running the command.
+    pub fn is_enabled<S>(
+        &self,
+        environment_name: S,
+        args: Option<&HashMap<String, String>>,
+        options: &ExecutionOptions,
+    ) -> crate::error::Result<ProfileStatus>
+    where
+        S: AsRef<str>,
+    {
+        let environment_name = environment_name.as_ref();
+        let environment = self.get_environment(environment_name)?;
+        let environment_args = self.transform_args(environment, args, None)?;
+        self._is_enabled(environment_name, environment, environment_args.as_ref(), options)
     }
 
     /// Enable the profile using the given environment.
     ///
+    /// Returns [`ActionOutcome::Unchanged`] if the profile is already enabled.
+    ///
     /// # Errors
     ///
     /// [`crate::error::Error::ProfileRequirementsNotMet`]: If the profile requirements are not met.
+    /// [`crate::error::Error::EnableIncomplete`]: If the `enable` command ran but failed or timed out, or the
+    /// `wait_until` or `verify` commands did not succeed afterwards, wrapping the error. The profile may be partly
+    /// enabled, and can be disabled with [`Profile::revert_enable`].
     /// [`crate::error::Error::CommandFailure`]: If any commands exit with a non-zero code.
+    /// [`crate::error::Error::Timeout`]: If any commands do not exit before their timeout.
     /// [`crate::error::Error::Io`]: If any IO errors occur when attempting to running the command.
-    pub fn enable<S>(&self, environment_name: S, args: Option<&HashMap<String, String>>) -> crate::error::Result<()>
+    pub fn enable<S>(
+        &self,
+        environment_name: S,
+        args: Option<&HashMap<String, String>>,
+        options: &ExecutionOptions,
+    ) -> crate::error::Result<ActionOutcome>
     where
         S: AsRef<str>,
     {
+        let environment_name = environment_name.as_ref();
         let environment = self.get_environment(environment_name)?;
-        let formattable_args = self.transform_args(environment, args);
-        self._can_enable(environment, formattable_args.as_ref())?;
-        if !self._is_enabled(environment, formattable_args.as_ref())? {
-            self._enable(environment, formattable_args.as_ref())?;
+        let environment_args = self.transform_args(environment, args, None)?;
+        self._can_enable(environment_name, environment, environment_args.as_ref(), options)?;
+        if self._is_enabled(environment_name, environment, environment_args.as_ref(), options)?
+            == ProfileStatus::Enabled
+        {
+            return Ok(ActionOutcome::Unchanged);
         }
+        let incomplete =
+            |source| crate::error::Error::EnableIncomplete { profile: self.name.to_owned(), source: Box::new(source) };
+        self._enable(environment_name, environment, environment_args.as_ref(), options).map_err(|err| match err {
+            crate::error::Error::CommandFailure { .. } | crate::error::Error::Timeout { .. } => incomplete(err),
+            err => err,
+        })?;
+        self._wait_until(environment_name, environment, environment_args.as_ref(), options).map_err(incomplete)?;
+        self._verify(environment_name, environment, environment_args.as_ref(), options).map_err(incomplete)?;
 
-        Ok(())
+        Ok(ActionOutcome::Changed)
     }
 
     /// Disable the profile using the given environment.
     ///
+    /// Returns [`ActionOutcome::Unchanged`] if the profile is not enabled
+    /// (including if the environment does not define an `is_enabled` command).
+    ///
     /// # Errors
     ///
     /// [`crate::error::Error::CommandFailure`]: If any commands exit with a non-zero code.
+    /// [`crate::error::Error::Timeout`]: If any commands do not exit before their timeout.
     /// [`crate::error::Error::Io`]: If any IO errors occur when attempting to running the command.
-    pub fn disable<S>(&self, environment_name: S, args: Option<&HashMap<String, String>>) -> crate::error::Result<()>
+    pub fn disable<S>(
+        &self,
+        environment_name: S,
+        args: Option<&HashMap<String, String>>,
+        options: &ExecutionOptions,
+    ) -> crate::error::Result<ActionOutcome>
     where
         S: AsRef<str>,
     {
+        let environment_name = environment_name.as_ref();
         let environment = self.get_environment(environment_name)?;
-        let formattable_args = self.transform_args(environment, args);
-        if self._is_enabled(environment, formattable_args.as_ref())? {
-            self._disable(environment, formattable_args.as_ref())?;
+        let environment_args = self.transform_args(environment, args, None)?;
+        if self._is_enabled(environment_name, environment, environment_args.as_ref(), options)?
+            != ProfileStatus::Enabled
+        {
+            return Ok(ActionOutcome::Unchanged);
         }
+        self._disable(environment_name, environment, environment_args.as_ref(), options)?;
 
-        Ok(())
+        Ok(ActionOutcome::Changed)
+    }
+
+    /// Disable the profile after it was enabled by [`Profile::enable`], e.g. when rolling back.
+    ///
+    /// Unlike [`Profile::disable`], the `disable` command is also run if the environment does not define
+    /// an `is_enabled` command, as the profile is known to have been enabled.
+    ///
+    /// # Errors
+    ///
+    /// [`crate::error::Error::CommandFailure`]: If any commands exit with a non-zero code.
+    /// [`crate::error::Error::Timeout`]: If any commands do not exit before their timeout.
+    /// [`crate::error::Error::Io`]: If any IO errors occur when attempting to running the command.
+    pub fn revert_enable<S>(
+        &self,
+        environment_name: S,
+        args: Option<&HashMap<String, String>>,
+        options: &ExecutionOptions,
+    ) -> crate::error::Result<ActionOutcome>
+    where
+        S: AsRef<str>,
+    {
+        let environment_name = environment_name.as_ref();
+        let environment = self.get_environment(environment_name)?;
+        let environment_args = self.transform_args(environment, args, None)?;
+        if self._is_enabled(environment_name, environment, environment_args.as_ref(), options)?
+            == ProfileStatus::Disabled
+        {
+            return Ok(ActionOutcome::Unchanged);
+        }
+        self._disable(environment_name, environment, environment_args.as_ref(), options)?;
+
+        Ok(ActionOutcome::Changed)
     }
 }
 
+/// Name of the directory next to a config file whose `*.toml` files are loaded with it
+/// (see: [`ProfileConfig::load`]).
+pub const CONFIG_DIRECTORY: &str = "conf.d";
+
 /// Collection of profiles.
+///
+/// Configs can be split across files (see: [`ProfileConfig::load`]):
+///
+/// ```toml
+/// include = ["common.toml", "work/*.toml"]
+/// ```
+///
+/// Included files are only loaded by [`ProfileConfig::load`]; deserializing a config ignores its `include` list.
 #[derive(Debug, serde::Deserialize)]
+#[serde(try_from = "RawProfileConfig")]
 pub struct ProfileConfig {
+    /// Environment templates that profile environments can extend, by name.
+    pub environments: HashMap<String, EnvironmentTemplate>,
     /// The list of profiles defined in the config.
     pub profiles: Vec<Profile>,
 }
 
+/// [`Profile`] as written in the config, before environment templates are applied.
+#[derive(Deserialize)]
+struct RawProfile {
+    #[serde(skip)]
+    source: Option<std::path::PathBuf>,
+    name: String,
+    aliases: Option<Vec<String>>,
+    extends: Option<String>,
+    parameters: Option<HashMap<String, String>>,
+    dependencies: Option<Vec<Dependency>>,
+    envs: Option<HashMap<String, EnvironmentTemplate>>,
+}
+
+/// [`ProfileConfig`] as written in the config, before environment templates are applied.
+#[derive(Default, Deserialize)]
+struct RawProfileConfig {
+    #[serde(default)]
+    include: Vec<String>,
+    #[serde(default)]
+    environments: HashMap<String, EnvironmentTemplate>,
+    #[serde(skip)]
+    environment_sources: HashMap<String, std::path::PathBuf>,
+    #[serde(default)]
+    profiles: Vec<RawProfile>,
+}
+
+impl RawProfileConfig {
+    /// Read a config file, then the files it includes (transitively), and merge them.
+    ///
+    /// Files that were already loaded (e.g. included twice) are skipped.
+    fn load(path: &std::path::Path, loaded: &mut HashSet<std::path::PathBuf>) -> crate::error::Result<Self> {
+        let read_error = |source| crate::error::Error::ConfigRead { path: path.to_owned(), source };
+        loaded.insert(std::fs::canonicalize(path).map_err(read_error)?);
+        let source = std::fs::read_to_string(path).map_err(read_error)?;
+        let mut config: RawProfileConfig = toml::from_str(&source)
+            .map_err(|source| crate::error::Error::ConfigParse { path: path.to_owned(), source })?;
+        for profile in config.profiles.iter_mut() {
+            profile.source = Some(path.to_owned());
+        }
+        config.environment_sources =
+            config.environments.keys().map(|name| (name.to_owned(), path.to_owned())).collect();
+
+        for included in Self::included_paths(path, &config.include)? {
+            if !loaded.contains(&std::fs::canonicalize(&included).unwrap_or_else(|_| included.to_owned())) {
+                config.merge(Self::load(&included, loaded)?)?;
+            }
+        }
+        Ok(config)
+    }
+
+    /// Read a config file, the files it includes, and the `*.toml` files in the [`CONFIG_DIRECTORY`] next to it.
+    fn load_with_directory(path: &std::path::Path) -> crate::error::Result<Self> {
+        let mut loaded = HashSet::new();
+        let mut config = Self::load(path, &mut loaded)?;
+        for path in Self::directory_paths(path)? {
+            if !loaded.contains(&std::fs::canonicalize(&path).unwrap_or_else(|_| path.to_owned())) {
+                config.merge(Self::load(&path, &mut loaded)?)?;
+            }
+        }
+        Ok(config)
+    }
+
+    /// Get the paths of the files a config file includes, in order.
+    ///
+    /// Relative paths are relative to the directory of the config file, and `~/` expands to the home directory.
+    /// Glob patterns (e.g. `work/*.toml`) match files in lexicographic order, and may not match any files.
+    fn included_paths(path: &std::path::Path, include: &[String]) -> crate::error::Result<Vec<std::path::PathBuf>> {
+        let directory = path.parent().unwrap_or(std::path::Path::new(""));
+        let mut paths = Vec::new();
+        for pattern in include {
+            let included = directory.join(crate::parameter::expand_home(std::path::Path::new(pattern)));
+            if !pattern.contains(['*', '?', '[']) {
+                paths.push(included);
+                continue;
+            }
+            let invalid = |message: String| crate::error::Error::InvalidInclude {
+                path: path.to_owned(),
+                pattern: pattern.to_owned(),
+                message,
+            };
+            let matches = glob::glob(&included.to_string_lossy()).map_err(|err| invalid(err.to_string()))?;
+            let mut matches = matches.collect::<Result<Vec<_>, _>>().map_err(|err| invalid(err.to_string()))?;
+            matches.sort();
+            paths.extend(matches.into_iter().filter(|path| path.is_file()));
+        }
+        Ok(paths)
+    }
+
+    /// Get the `*.toml` files in the [`CONFIG_DIRECTORY`] next to a config file, in lexicographic order.
+    fn directory_paths(path: &std::path::Path) -> crate::error::Result<Vec<std::path::PathBuf>> {
+        let directory = path.parent().unwrap_or(std::path::Path::new("")).join(CONFIG_DIRECTORY);
+        if !directory.is_dir() {
+            return Ok(Vec::new());
+        }
+        let read_error = |source| crate::error::Error::ConfigRead { path: directory.to_owned(), source };
+        let mut paths = std::fs::read_dir(&directory)
+            .map_err(read_error)?
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(read_error)?;
+        paths.retain(|path| path.is_file() && path.extension().is_some_and(|extension| extension == "toml"));
+        paths.sort();
+        Ok(paths)
+    }
+
+    /// Merge the profiles and environment templates of another config into this one.
+    ///
+    /// # Errors
+    ///
+    /// [`crate::error::Error::DuplicateProfile`]: If a profile with the same name is defined in both configs.
+    /// [`crate::error::Error::DuplicateTemplate`]: If an environment template with the same name is defined
+    /// in both configs.
+    fn merge(&mut self, other: RawProfileConfig) -> crate::error::Result<()> {
+        for (name, template) in other.environments {
+            let source = other.environment_sources.get(&name).cloned().unwrap_or_default();
+            if let Some(first) = self.environment_sources.get(&name) {
+                return Err(crate::error::Error::DuplicateTemplate { name, first: first.to_owned(), second: source });
+            }
+            self.environment_sources.insert(name.to_owned(), source);
+            self.environments.insert(name, template);
+        }
+        for profile in other.profiles {
+            if let Some(first) = self.profiles.iter().find(|existing| existing.name == profile.name) {
+                return Err(crate::error::Error::DuplicateProfile {
+                    name: profile.name,
+                    first: first.source.clone().unwrap_or_default(),
+                    second: profile.source.unwrap_or_default(),
+                });
+            }
+            self.profiles.push(profile);
+        }
+        Ok(())
+    }
+
+    /// Overlay the profiles and environment templates of a higher-priority config onto this one.
+    ///
+    /// Profiles and templates with the same name replace the ones in this config (in place, so profiles keep their
+    /// order), others are added.
+    fn overlay(&mut self, other: RawProfileConfig) {
+        for (name, template) in other.environments {
+            if let Some(source) = other.environment_sources.get(&name) {
+                self.environment_sources.insert(name.to_owned(), source.to_owned());
+            }
+            self.environments.insert(name, template);
+        }
+        for profile in other.profiles {
+            match self.profiles.iter_mut().find(|existing| existing.name == profile.name) {
+                Some(existing) => *existing = profile,
+                None => self.profiles.push(profile),
+            }
+        }
+    }
+}
+
+impl TryFrom<RawProfileConfig> for ProfileConfig {
+    type Error = crate::error::Error;
+
+    /// Apply the environment templates that profile environments extend, then the profiles that profiles extend.
+    fn try_from(config: RawProfileConfig) -> Result<Self, Self::Error> {
+        let templates = config.environments;
+        let mut profiles = Vec::with_capacity(config.profiles.len());
+        let mut overrides = Vec::with_capacity(config.profiles.len());
+        for profile in config.profiles {
+            let invalid =
+                |message: String| crate::error::Error::InvalidProfile { profile: profile.name.to_owned(), message };
+            let envs = profile
+                .envs
+                .map(|envs| {
+                    envs.into_iter()
+                        .map(|(name, environment)| {
+                            let extends = environment.extends.clone();
+                            environment
+                                .apply(&templates)
+                                .and_then(ProfileEnvironment::try_from)
+                                .map(|environment| (name.to_owned(), ProfileEnvironment { extends, ..environment }))
+                                .map_err(|message| invalid(format!("environment {}: {}", name, message)))
+                        })
+                        .collect::<crate::error::Result<HashMap<_, _>>>()
+                })
+                .transpose()?;
+            match profile.extends.as_ref() {
+                Some(extends) if envs.is_some() || profile.dependencies.is_some() => {
+                    return Err(invalid(format!(
+                        "profiles that extend another profile ({}) can only override parameters",
+                        extends,
+                    )));
+                },
+                None if profile.parameters.is_some() => {
+                    return Err(invalid(
+                        "parameters can only be overridden by profiles that extend another profile".to_owned(),
+                    ));
+                },
+                _ => {},
+            }
+            overrides.push(profile.parameters);
+            profiles.push(Profile {
+                source: profile.source,
+                name: profile.name,
+                aliases: profile.aliases,
+                extends: profile.extends,
+                dependencies: profile.dependencies,
+                envs,
+                detected_environment: std::sync::OnceLock::new(),
+            });
+        }
+
+        let mut resolved = vec![false; profiles.len()];
+        for index in 0..profiles.len() {
+            Self::inherit(&mut profiles, &overrides, &mut resolved, index, &mut Vec::new())?;
+        }
+        Ok(ProfileConfig { environments: templates, profiles })
+    }
+}
+
 impl ProfileConfig {
+    /// Load a config file, the files it includes, and the `*.toml` files in the [`CONFIG_DIRECTORY`] next to it.
+    ///
+    /// Files are loaded in order: the config file, then the files it includes (in the order of its `include` list,
+    /// transitively), then the files in the config directory (and the files they include). Profiles and environment
+    /// templates from every file are merged, so profiles can depend on and extend profiles from other files.
+    /// Files that are included more than once are only loaded once.
+    ///
+    /// # Errors
+    ///
+    /// [`crate::error::Error::ConfigRead`]: If a config file cannot be read.
+    /// [`crate::error::Error::ConfigParse`]: If a config file is not a valid config.
+    /// [`crate::error::Error::InvalidInclude`]: If an include pattern is not valid.
+    /// [`crate::error::Error::DuplicateProfile`]: If a profile is defined in more than one file.
+    /// [`crate::error::Error::DuplicateTemplate`]: If an environment template is defined in more than one file.
+    /// [`crate::error::Error::InvalidProfile`]: If an environment template or profile cannot be applied.
+    pub fn load<P: AsRef<std::path::Path>>(path: P) -> crate::error::Result<Self> {
+        ProfileConfig::try_from(RawProfileConfig::load_with_directory(path.as_ref())?)
+    }
+
+    /// Load config files as layers (e.g. a system config, then a user config), from the lowest priority to the highest.
+    ///
+    /// Each layer is loaded like [`ProfileConfig::load`]. Profiles and environment templates of a layer replace the
+    /// ones with the same name in lower layers, so a user config can override system profiles.
+    ///
+    /// # Errors
+    ///
+    /// Same as [`ProfileConfig::load`], for each layer. Names defined more than once within a layer are errors,
+    /// but names defined in several layers are not.
+    pub fn load_layers<I, P>(paths: I) -> crate::error::Result<Self>
+    where
+        I: IntoIterator<Item = P>,
+        P: AsRef<std::path::Path>,
+    {
+        let mut config = RawProfileConfig::default();
+        for path in paths {
+            config.overlay(RawProfileConfig::load_with_directory(path.as_ref())?);
+        }
+        ProfileConfig::try_from(config)
+    }
+
+    /// Copy the environments and dependencies of the profile a profile extends (once that profile is resolved),
+    /// and override the defaults of its parameters.
+    ///
+    /// Overridden parameters are no longer required, and no longer read from a secret source.
+    ///
+    /// # Errors
+    ///
+    /// [`crate::error::Error::InvalidProfile`]: If the extended profile is not defined, profiles extend each other,
+    /// or an overridden parameter is not defined by any environment of the extended profile.
+    fn inherit(
+        profiles: &mut [Profile],
+        overrides: &[Option<HashMap<String, String>>],
+        resolved: &mut [bool],
+        index: usize,
+        path: &mut Vec<usize>,
+    ) -> crate::error::Result<()> {
+        let Some(extends) = profiles[index].extends.clone().filter(|_| !resolved[index]) else {
+            resolved[index] = true;
+            return Ok(());
+        };
+        let invalid = |profiles: &[Profile], message: String| crate::error::Error::InvalidProfile {
+            profile: profiles[index].name.to_owned(),
+            message,
+        };
+        if let Some(start) = path.iter().position(|visited| *visited == index) {
+            let cycle = path[start..].iter().chain([&index]).map(|visited| profiles[*visited].name.as_str());
+            return Err(invalid(
+                profiles,
+                format!("profiles extend each other: {}", cycle.collect::<Vec<_>>().join(" -> ")),
+            ));
+        }
+        let parent = profiles
+            .iter()
+            .position(|profile| {
+                profile.name == extends || profile.aliases.iter().flatten().any(|alias| *alias == extends)
+            })
+            .ok_or_else(|| invalid(profiles, format!("extended profile {} not found", extends)))?;
+
+        path.push(index);
+        Self::inherit(profiles, overrides, resolved, parent, path)?;
+        path.pop();
+
+        let mut envs = profiles[parent].envs.clone();
+        for (name, value) in overrides[index].iter().flatten() {
+            let parameters = envs
+                .iter_mut()
+                .flatten()
+                .filter_map(|(_, environment)| {
+                    environment.parameters.as_mut().and_then(|parameters| parameters.get_mut(name))
+                })
+                .collect::<Vec<_>>();
+            if parameters.is_empty() {
+                return Err(invalid(
+                    profiles,
+                    format!("parameter {} is not defined by any environment of profile {}", name, extends),
+                ));
+            }
+            for parameter in parameters {
+                parameter.default = Some(value.to_owned());
+                parameter.required = false;
+                parameter.source = None;
+            }
+        }
+        profiles[index].dependencies = profiles[parent].dependencies.clone();
+        profiles[index].envs = envs;
+        resolved[index] = true;
+        Ok(())
+    }
+
+    /// Validate the config and return every problem found.
+    ///
+    /// In addition to the checks in [`Profile::validate`], checks that:
+    ///
+    /// 1. Profile names and aliases are unique across the config.
+    /// 2. Dependencies refer to defined profiles, and to environments those profiles define (`<profile>:<env>`).
+    /// 3. Dependencies do not form cycles.
+    pub fn validate(&self) -> Vec<crate::validation::ValidationIssue> {
+        let issue = |index: usize, kind: crate::validation::ValidationIssueKind| crate::validation::ValidationIssue {
+            index,
+            profile: self.profiles[index].name.to_owned(),
+            kind,
+        };
+        let mut issues = Vec::new();
+
+        let mut owners = HashMap::<&str, usize>::with_capacity(self.profiles.len());
+        for (index, profile) in self.profiles.iter().enumerate() {
+            let names = std::iter::once(&profile.name).chain(profile.aliases.iter().flatten());
+            for name in names {
+                match owners.get(name.as_str()) {
+                    Some(other) if *other != index => issues.push(issue(
+                        index,
+                        crate::validation::ValidationIssueKind::DuplicateName {
+                            name: name.to_owned(),
+                            other: self.profiles[*other].name.to_owned(),
+                        },
+                    )),
+                    Some(_) => {},
+                    None => {
+                        owners.insert(name, index);
+                    },
+                }
+            }
+        }
+
+        let profile_map = self.profile_map();
+        for (index, profile) in self.profiles.iter().enumerate() {
+            issues.extend(profile.validate().into_iter().map(|kind| issue(index, kind)));
+
+            for dependency in profile.dependencies.iter().flatten() {
+                match profile_map.get(dependency.name.as_str()) {
+                    None => issues.push(issue(
+                        index,
+                        crate::validation::ValidationIssueKind::UnknownDependency {
+                            dependency: dependency.name.to_owned(),
+                        },
+                    )),
+                    Some(dependency_profile) => {
+                        if let Some(env_name) = dependency.env_name.as_ref() {
+                            if !dependency_profile.envs.as_ref().is_some_and(|envs| envs.contains_key(env_name)) {
+                                issues.push(issue(
+                                    index,
+                                    crate::validation::ValidationIssueKind::UnknownDependencyEnvironment {
+                                        dependency: dependency.name.to_owned(),
+                                        environment: env_name.to_owned(),
+                                    },
+                                ));
+                            }
+                        }
+                    },
+                }
+            }
+        }
+
+        for path in self.find_dependency_cycles(&profile_map) {
+            if let Some(index) = self.profiles.iter().position(|profile| profile.name == path[0]) {
+                issues.push(issue(index, crate::validation::ValidationIssueKind::DependencyCycle { path }));
+            }
+        }
+
+        issues
+    }
+
+    /// Validate the config.
+    ///
+    /// # Errors
+    ///
+    /// [`crate::error::Error::InvalidConfig`]: If any error-level problems are found (see: [`ProfileConfig::validate`]).
+    /// The error contains every problem found, including warnings.
     pub fn is_valid(&self) -> crate::error::Result<()> {
+        let issues = self.validate();
+        if issues.iter().any(|issue| issue.severity() == crate::validation::Severity::Error) {
+            Err(crate::error::Error::InvalidConfig { issues })
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Find dependency cycles, ignoring undefined dependencies.
+    ///
+    /// Each cycle is reported once, as the path of profile names starting and ending with the same profile.
+    fn find_dependency_cycles(&self, profile_map: &HashMap<&str, &Profile>) -> Vec<Vec<String>> {
+        fn visit<'a>(
+            profile_map: &HashMap<&str, &'a Profile>,
+            profile: &'a Profile,
+            path: &mut Vec<&'a str>,
+            visited: &mut HashSet<&'a str>,
+            cycles: &mut Vec<Vec<String>>,
+        ) {
+            if let Some(position) = path.iter().position(|name| *name == profile.name) {
+                let mut cycle = path[position..].iter().map(ToString::to_string).collect::<Vec<_>>();
+                cycle.push(profile.name.to_owned());
+                cycles.push(cycle);
+                return;
+            }
+            if !visited.insert(&profile.name) {
+                return;
+            }
+
+            path.push(&profile.name);
+            for dependency in profile.dependencies.iter().flatten() {
+                if let Some(dependency_profile) = profile_map.get(dependency.name.as_str()) {
+                    visit(profile_map, dependency_profile, path, visited, cycles);
+                }
+            }
+            path.pop();
+        }
+
+        let mut cycles = Vec::new();
+        let mut visited = HashSet::new();
         for profile in self.profiles.iter() {
-            profile.is_valid()?;
+            visit(profile_map, profile, &mut Vec::new(), &mut visited, &mut cycles);
+        }
+        cycles
+    }
+
+    /// Map profile names and aliases to profiles.
+    ///
+    /// If a name or alias is defined more than once, it maps to the first profile that defines it,
+    /// like [`ProfileConfig::get_profile`].
+    pub fn profile_map(&self) -> HashMap<&str, &Profile> {
+        let mut profile_map = HashMap::<&str, &Profile>::with_capacity(self.profiles.len());
+        for profile in self.profiles.iter() {
+            profile_map.entry(&profile.name).or_insert(profile);
+            if let Some(aliases) = profile.aliases.as_ref() {
+                for alias in aliases.iter() {
+                    profile_map.entry(alias).or_insert(profile);
+                }
+            }
+        }
+        profile_map
+    }
+
+    /// Get a profile by name or alias.
+    pub fn get_profile<S: AsRef<str>>(&self, profile_name: S) -> Option<&Profile> {
+        let profile_name = profile_name.as_ref();
+        self.profiles.iter().find(|profile| {
+            profile.name == profile_name
+                || profile.aliases.as_ref().is_some_and(|aliases| aliases.iter().any(|alias| alias == profile_name))
+        })
+    }
+
+    /// Resolve a profile and all of its transitive dependencies.
+    ///
+    /// Dependencies are walked depth-first and returned in topological order, i.e. every profile
+    /// comes after all of the profiles it depends on, so the list can be enabled front-to-back and
+    /// disabled back-to-front. Dependencies shared by multiple profiles are only included once per
+    /// environment. Composition profiles are walked but not included, as they have no commands to run.
+    ///
+    /// Each profile is paired with the environment name from the dependency declaration
+    /// (e.g. `linux-nmcli` in `wifi:linux-nmcli`), if any.
+    ///
+    /// # Errors
+    ///
+    /// [`crate::error::Error::ProfileNotFound`]: If the profile name or alias is not defined.
+    /// [`crate::error::Error::InvalidProfile`]: If a profile depends on an undefined profile.
+    /// [`crate::error::Error::DependencyCycle`]: If the dependencies form a cycle.
+    pub fn resolve_dependencies<S: AsRef<str>>(
+        &self,
+        profile_name: S,
+    ) -> crate::error::Result<Vec<(&Profile, Option<&str>)>> {
+        let profile = self
+            .get_profile(profile_name.as_ref())
+            .ok_or_else(|| crate::error::Error::ProfileNotFound { name: profile_name.as_ref().to_owned() })?;
+        self.resolve_profile_dependencies(profile)
+    }
+
+    /// Resolve a profile of the config and all of its transitive dependencies
+    /// (see: [`ProfileConfig::resolve_dependencies`]).
+    ///
+    /// # Errors
+    ///
+    /// [`crate::error::Error::InvalidProfile`]: If a profile depends on an undefined profile.
+    /// [`crate::error::Error::DependencyCycle`]: If the dependencies form a cycle.
+    pub(crate) fn resolve_profile_dependencies<'a>(
+        &'a self,
+        profile: &'a Profile,
+    ) -> crate::error::Result<Vec<(&'a Profile, Option<&'a str>)>> {
+        let mut resolved = Vec::new();
+        Self::visit_dependencies(
+            &self.profile_map(),
+            profile,
+            None,
+            &mut Vec::new(),
+            &mut HashSet::new(),
+            &mut resolved,
+        )?;
+        Ok(resolved)
+    }
+
+    /// Depth-first, post-order walk of a profile's dependencies (see: [`ProfileConfig::resolve_dependencies`]).
+    ///
+    /// `path` holds the names of the profiles currently being walked and is used to detect cycles.
+    fn visit_dependencies<'a>(
+        profile_map: &HashMap<&str, &'a Profile>,
+        profile: &'a Profile,
+        environment_name: Option<&'a str>,
+        path: &mut Vec<&'a str>,
+        visited: &mut HashSet<(&'a str, Option<&'a str>)>,
+        resolved: &mut Vec<(&'a Profile, Option<&'a str>)>,
+    ) -> crate::error::Result<()> {
+        if let Some(position) = path.iter().position(|name| *name == profile.name) {
+            let mut cycle = path[position..].iter().map(ToString::to_string).collect::<Vec<_>>();
+            cycle.push(profile.name.to_owned());
+            return Err(crate::error::Error::DependencyCycle { path: cycle });
+        }
+        if visited.contains(&(profile.name.as_str(), environment_name)) {
+            return Ok(());
         }
 
+        path.push(&profile.name);
+        if let Some(dependencies) = profile.dependencies.as_ref() {
+            for dependency in dependencies {
+                let dependency_profile =
+                    profile_map.get(dependency.name.as_str()).ok_or_else(|| crate::error::Error::InvalidProfile {
+                        profile: profile.name.to_owned(),
+                        message: format!("Dependency {} is not defined", dependency.name),
+                    })?;
+                Self::visit_dependencies(
+                    profile_map,
+                    dependency_profile,
+                    dependency.env_name.as_deref(),
+                    path,
+                    visited,
+                    resolved,
+                )?;
+            }
+        }
+        path.pop();
+
+        visited.insert((profile.name.as_str(), environment_name));
+        if !profile.is_composition_profile() {
+            resolved.push((profile, environment_name));
+        }
         Ok(())
     }
 }
@@ -0,0 +1,41 @@
+use std::borrow::Cow;
+
+/// Text that secrets are replaced with when they are logged, shown or reported.
+pub const REDACTED: &str = "***";
+
+/// Masks secrets (e.g. the args of secret parameters) in text, like command output and error messages.
+///
+/// Secrets are replaced with [`REDACTED`] wherever they appear, longest first so that secrets containing
+/// other secrets are masked in full. Empty secrets are ignored.
+#[derive(Clone, Debug, Default)]
+pub struct Redactor {
+    secrets: Vec<String>,
+}
+
+impl Redactor {
+    /// Create a redactor that masks the given secrets.
+    pub fn new<I, S>(secrets: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        let mut secrets = secrets.into_iter().map(Into::into).filter(|secret| !secret.is_empty()).collect::<Vec<_>>();
+        secrets.sort_by(|a, b| b.len().cmp(&a.len()).then_with(|| a.cmp(b)));
+        secrets.dedup();
+        Redactor { secrets }
+    }
+
+    /// Whether the redactor has no secrets to mask.
+    pub fn is_empty(&self) -> bool {
+        self.secrets.is_empty()
+    }
+
+    /// Mask the secrets in the given text.
+    pub fn redact<'a>(&self, text: &'a str) -> Cow<'a, str> {
+        let mut redacted = Cow::Borrowed(text);
+        for secret in self.secrets.iter().filter(|secret| text.contains(secret.as_str())) {
+            redacted = Cow::Owned(redacted.replace(secret.as_str(), REDACTED));
+        }
+        redacted
+    }
+}
@@ -0,0 +1,99 @@
+use std::{
+    sync::{Arc, Mutex, PoisonError},
+    time::Duration,
+};
+
+use crate::{
+    plan::{ProfileAction, StepAction},
+    profile::CommandPhase,
+};
+
+fn serialize_seconds<S>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: serde::Serializer,
+{
+    serializer.serialize_f64(duration.as_secs_f64())
+}
+
+/// Record of a profile command that was run.
+#[derive(Clone, Debug, serde::Serialize)]
+pub struct CommandRecord {
+    /// Name of the profile the command belongs to.
+    pub profile: String,
+    /// Name of the environment the command belongs to.
+    pub environment: String,
+    /// Phase the command was run in.
+    pub phase: CommandPhase,
+    /// Rendered command, with args injected (args of secret parameters are masked).
+    pub command: String,
+    /// Exit code of the command, if it exited normally.
+    pub exit_code: Option<i32>,
+    /// Captured standard output, with secrets masked.
+    pub stdout: String,
+    /// Captured standard error, with secrets masked.
+    pub stderr: String,
+    /// Time the command ran for (in seconds, when serialized).
+    #[serde(serialize_with = "serialize_seconds")]
+    pub duration: Duration,
+    /// Error running the command (e.g. it timed out), if any.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Collects a [`CommandRecord`] for every command run with the
+/// [`crate::profile::ExecutionOptions`] it is set on.
+///
+/// Clones share the same records.
+#[derive(Clone, Debug, Default)]
+pub struct CommandRecorder(Arc<Mutex<Vec<CommandRecord>>>);
+
+impl CommandRecorder {
+    /// Create a recorder without any records.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a record.
+    pub fn record(&self, record: CommandRecord) {
+        self.0.lock().unwrap_or_else(PoisonError::into_inner).push(record);
+    }
+
+    /// Remove and return all records, in the order the commands were run.
+    pub fn take(&self) -> Vec<CommandRecord> {
+        std::mem::take(&mut *self.0.lock().unwrap_or_else(PoisonError::into_inner))
+    }
+}
+
+/// Report of a single step of a [`crate::plan::Plan`].
+#[derive(Clone, Debug, serde::Serialize)]
+pub struct StepReport {
+    /// Name of the profile.
+    pub profile: String,
+    /// Name of the environment used.
+    pub environment: String,
+    /// Action run on the profile.
+    pub action: StepAction,
+    /// Whether the `enable` (or `disable`) command was skipped,
+    /// as the profile was already enabled (or not enabled).
+    pub skipped: bool,
+    /// Commands run by the step, in order.
+    pub commands: Vec<CommandRecord>,
+    /// Error that failed the step, if any.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Report of executing a [`crate::plan::Plan`].
+#[derive(Clone, Debug, serde::Serialize)]
+pub struct ExecutionReport {
+    /// Name of the profile the plan was made for.
+    pub profile: String,
+    /// The planned action.
+    pub action: ProfileAction,
+    /// Whether every step succeeded.
+    pub success: bool,
+    /// Executed steps, in order (including the step that failed, if any).
+    pub steps: Vec<StepReport>,
+    /// Steps run to roll back profiles enabled before a failure, in order.
+    pub rollback: Vec<StepReport>,
+}
@@ -0,0 +1,126 @@
+use std::collections::{HashMap, HashSet};
+
+/// Severity of a [`ValidationIssue`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Severity {
+    /// The config cannot be used as-is.
+    Error,
+    /// The config can be used, but likely contains a mistake.
+    Warning,
+}
+
+impl std::fmt::Display for Severity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Severity::Error => write!(f, "error"),
+            Severity::Warning => write!(f, "warning"),
+        }
+    }
+}
+
+/// Kinds of problems found when validating a [`crate::profile::ProfileConfig`].
+#[derive(Clone, Debug, thiserror::Error)]
+pub enum ValidationIssueKind {
+    /// Profile defines neither environments nor dependencies.
+    #[error("One or more environments or dependencies must be defined")]
+    MissingEnvironments,
+    /// Profile depends on a profile name or alias that is not defined.
+    #[error("Dependency {dependency} is not defined")]
+    UnknownDependency { dependency: String },
+    /// Profile depends on an environment (`<profile>:<env>`) the dependency does not define.
+    #[error("Dependency {dependency} does not define environment {environment}")]
+    UnknownDependencyEnvironment { dependency: String, environment: String },
+    /// Profile dependencies form a cycle.
+    #[error("Dependency cycle detected: {}", path.join(" -> "))]
+    DependencyCycle { path: Vec<String> },
+    /// Profile name or alias is already used by another profile.
+    #[error("Name or alias {name} is already used by profile {other}")]
+    DuplicateName { name: String, other: String },
+    /// Environment verifies the profile is enabled, but defines neither a `verify` nor an `is_enabled` command.
+    #[error("Environment {environment} must define a verify or is_enabled command to verify the profile")]
+    MissingVerifyCommand { environment: String },
+    /// Environment parameter is not used by any of the environment's commands.
+    #[error("Parameter {parameter} of environment {environment} is not used by any command")]
+    UnusedParameter { environment: String, parameter: String },
+    /// Command uses a placeholder that is not declared in the environment's parameters.
+    #[error("Command {command} of environment {environment} uses undeclared parameter {parameter}")]
+    UndeclaredParameter { environment: String, command: String, parameter: String },
+    /// Command uses a placeholder, but is not interpolated as the environment declares no parameters.
+    #[error(
+        "Command {command} of environment {environment} uses placeholder {{{parameter}}}, but the environment declares \
+         no parameters so it is not interpolated"
+    )]
+    UninterpolatedPlaceholder { environment: String, command: String, parameter: String },
+    /// Parameter schema is inconsistent, e.g. the default value does not match the parameter type.
+    #[error("Parameter {parameter} of environment {environment} is invalid: {reason}")]
+    InvalidParameter { environment: String, parameter: String, reason: String },
+    /// Command uses a placeholder filter that does not exist.
+    #[error("Command {command} of environment {environment} uses unknown filter {filter}")]
+    UnknownFilter { environment: String, command: String, filter: String },
+    /// Command is interactive, but only `enable` and `disable` commands can be run interactively.
+    #[error("Command {command} of environment {environment} cannot be interactive, its output is captured")]
+    IgnoredInteractive { environment: String, command: String },
+}
+
+impl ValidationIssueKind {
+    /// Get the severity of the problem.
+    pub fn severity(&self) -> Severity {
+        match self {
+            ValidationIssueKind::UnusedParameter { .. }
+            | ValidationIssueKind::UninterpolatedPlaceholder { .. }
+            | ValidationIssueKind::IgnoredInteractive { .. } => Severity::Warning,
+            _ => Severity::Error,
+        }
+    }
+}
+
+/// Problem found when validating a [`crate::profile::ProfileConfig`].
+#[derive(Clone, Debug, thiserror::Error)]
+#[error("Profile {profile}: {kind}")]
+pub struct ValidationIssue {
+    /// Index of the profile in [`crate::profile::ProfileConfig::profiles`].
+    pub index: usize,
+    /// Name of the profile.
+    pub profile: String,
+    /// The problem.
+    pub kind: ValidationIssueKind,
+}
+
+impl ValidationIssue {
+    /// Get the severity of the problem.
+    pub fn severity(&self) -> Severity {
+        self.kind.severity()
+    }
+}
+
+/// Problem found when checking args against the parameters of a profile environment.
+#[derive(Clone, Debug, thiserror::Error)]
+pub enum ArgumentIssue {
+    /// Arg is not a parameter of any of the profiles (or of the profile environment, when checking a single profile).
+    #[error("Unknown arg {name}")]
+    Unknown { name: String },
+    /// Required arg is missing.
+    #[error("Profile {profile}: missing required arg {name}")]
+    Missing { profile: String, name: String },
+    /// Arg does not match its parameter.
+    #[error("Profile {profile}: invalid arg {name}={value:?}, {reason}")]
+    Invalid { profile: String, name: String, value: String, reason: String },
+}
+
+/// Check for args that are not one of the given parameters, e.g. a misspelled parameter name.
+///
+/// Returns an [`ArgumentIssue::Unknown`] for every unknown arg, sorted by name.
+pub fn check_unknown_args<'a, I>(args: Option<&HashMap<String, String>>, parameters: I) -> Vec<ArgumentIssue>
+where
+    I: IntoIterator<Item = &'a str>,
+{
+    let parameters = parameters.into_iter().collect::<HashSet<_>>();
+    let mut unknown = args
+        .into_iter()
+        .flatten()
+        .map(|(name, _)| name)
+        .filter(|name| !parameters.contains(name.as_str()))
+        .collect::<Vec<_>>();
+    unknown.sort();
+    unknown.into_iter().map(|name| ArgumentIssue::Unknown { name: name.to_owned() }).collect()
+}
@@ -0,0 +1,173 @@
+use std::{collections::HashMap, ops::Range};
+
+use libnprofile::{
+    profile::ProfileConfig,
+    validation::{Severity, ValidationIssueKind},
+};
+use toml::Spanned;
+
+/// Mirror of [`libnprofile::profile::ProfileEnvironment`] that records where values are defined.
+#[derive(Debug, serde::Deserialize)]
+struct SpannedEnvironment {
+    parameters: Option<HashMap<String, Spanned<toml::Value>>>,
+    can_enable: Option<Spanned<toml::Value>>,
+    is_enabled: Option<Spanned<toml::Value>>,
+    enable: Option<Spanned<toml::Value>>,
+    disable: Option<Spanned<toml::Value>>,
+    wait_until: Option<Spanned<toml::Value>>,
+    verify: Option<Spanned<toml::Value>>,
+}
+
+impl SpannedEnvironment {
+    fn command(&self, command: &str) -> Option<&Spanned<toml::Value>> {
+        match command {
+            "can_enable" => self.can_enable.as_ref(),
+            "is_enabled" => self.is_enabled.as_ref(),
+            "enable" => self.enable.as_ref(),
+            "disable" => self.disable.as_ref(),
+            "wait_until" => self.wait_until.as_ref(),
+            "verify" => self.verify.as_ref(),
+            _ => None,
+        }
+    }
+}
+
+/// Mirror of [`libnprofile::profile::Profile`] that records where values are defined.
+#[derive(Debug, serde::Deserialize)]
+struct SpannedProfile {
+    name: Spanned<String>,
+    aliases: Option<Vec<Spanned<String>>>,
+    dependencies: Option<Vec<Spanned<String>>>,
+    envs: Option<HashMap<String, SpannedEnvironment>>,
+}
+
+impl SpannedProfile {
+    fn alias(&self, alias: &str) -> Option<Range<usize>> {
+        self.aliases.iter().flatten().find(|value| value.get_ref() == alias).map(Spanned::span)
+    }
+
+    fn dependency<F: Fn(&str) -> bool>(&self, predicate: F) -> Option<Range<usize>> {
+        self.dependencies.iter().flatten().find(|value| predicate(value.get_ref())).map(Spanned::span)
+    }
+
+    fn environment(&self, environment: &str) -> Option<&SpannedEnvironment> {
+        self.envs.as_ref().and_then(|envs| envs.get(environment))
+    }
+
+    /// Find the location of the value that caused a validation issue, falling back to the profile name.
+    fn locate(&self, kind: &ValidationIssueKind) -> Range<usize> {
+        let span = match kind {
+            ValidationIssueKind::UnknownDependency { dependency } => {
+                self.dependency(|value| value.split(':').next() == Some(dependency.as_str()))
+            },
+            ValidationIssueKind::UnknownDependencyEnvironment { dependency, environment } => {
+                self.dependency(|value| value == format!("{}:{}", dependency, environment))
+            },
+            ValidationIssueKind::DependencyCycle { path } => {
+                path.get(1).and_then(|next| self.dependency(|value| value.split(':').next() == Some(next.as_str())))
+            },
+            ValidationIssueKind::DuplicateName { name, .. } if self.name.get_ref() != name => self.alias(name),
+            ValidationIssueKind::MissingVerifyCommand { environment } => {
+                self.environment(environment).and_then(|env| env.verify.as_ref()).map(Spanned::span)
+            },
+            ValidationIssueKind::UnusedParameter { environment, parameter }
+            | ValidationIssueKind::InvalidParameter { environment, parameter, .. } => self
+                .environment(environment)
+                .and_then(|env| env.parameters.as_ref())
+                .and_then(|parameters| parameters.get(parameter))
+                .map(Spanned::span),
+            ValidationIssueKind::UndeclaredParameter { environment, command, .. }
+            | ValidationIssueKind::UninterpolatedPlaceholder { environment, command, .. }
+            | ValidationIssueKind::UnknownFilter { environment, command, .. }
+            | ValidationIssueKind::IgnoredInteractive { environment, command } => {
+                self.environment(environment).and_then(|env| env.command(command)).map(Spanned::span)
+            },
+            _ => None,
+        };
+        span.unwrap_or_else(|| self.name.span())
+    }
+}
+
+/// Mirror of [`libnprofile::profile::ProfileConfig`] that records where values are defined.
+#[derive(Debug, serde::Deserialize)]
+struct SpannedConfig {
+    #[serde(default)]
+    profiles: Vec<SpannedProfile>,
+}
+
+/// Source and spans of a config file, for locating validation issues.
+struct SourceFile {
+    source: String,
+    spanned: Option<SpannedConfig>,
+}
+
+impl SourceFile {
+    fn read(path: &std::path::Path) -> Option<Self> {
+        let source = std::fs::read_to_string(path).ok()?;
+        let spanned = toml::from_str::<SpannedConfig>(source.as_str()).ok();
+        Some(SourceFile { source, spanned })
+    }
+
+    /// Find the line and column of the value that caused a validation issue for a profile defined in the file.
+    fn locate(&self, profile_name: &str, kind: &ValidationIssueKind) -> Option<(usize, usize)> {
+        let profile = self.spanned.as_ref()?.profiles.iter().find(|profile| profile.name.get_ref() == profile_name)?;
+        Some(line_column(&self.source, profile.locate(kind).start))
+    }
+}
+
+/// Convert a byte offset into a 1-based line and column.
+fn line_column(source: &str, offset: usize) -> (usize, usize) {
+    let before = &source[..offset.min(source.len())];
+    let line = before.matches('\n').count() + 1;
+    let column = before.rsplit('\n').next().map(|line| line.chars().count()).unwrap_or(0) + 1;
+    (line, column)
+}
+
+/// Lint config files (loaded as layers), and the files they include, without running any profile commands.
+///
+/// Prints every validation error and warning with the file, line and column it was found at,
+/// and returns whether the config passed (warnings only fail the check if `strict` is set).
+pub(crate) fn check_config(config_paths: &[std::path::PathBuf], strict: bool) -> bool {
+    let path = config_paths.iter().map(|path| path.display().to_string()).collect::<Vec<_>>().join(", ");
+    let config = match ProfileConfig::load_layers(config_paths) {
+        Ok(config) => config,
+        Err(libnprofile::error::Error::ConfigParse { path, source: err }) => {
+            let source = std::fs::read_to_string(&path).unwrap_or_default();
+            let (line, column) = line_column(&source, err.span().map(|span| span.start).unwrap_or(0));
+            println!("{}:{}:{}: {}: {}", path.display(), line, column, Severity::Error, err.message().trim());
+            return false;
+        },
+        Err(err) => {
+            println!("{}: {}: {}", path, Severity::Error, err);
+            return false;
+        },
+    };
+
+    let issues = config.validate();
+    let mut files = HashMap::new();
+    for issue in issues.iter() {
+        let profile = &config.profiles[issue.index];
+        let Some(file) = profile.source.as_deref() else {
+            println!("{}: {}: {}", path, issue.severity(), issue);
+            continue;
+        };
+        let location = files
+            .entry(file)
+            .or_insert_with(|| SourceFile::read(file))
+            .as_ref()
+            .and_then(|source| source.locate(&profile.name, &issue.kind));
+        match location {
+            Some((line, column)) => {
+                println!("{}:{}:{}: {}: {}", file.display(), line, column, issue.severity(), issue)
+            },
+            None => println!("{}: {}: {}", file.display(), issue.severity(), issue),
+        }
+    }
+
+    let errors = issues.iter().filter(|issue| issue.severity() == Severity::Error).count();
+    let warnings = issues.len() - errors;
+    if !issues.is_empty() {
+        println!("{}: {} error(s), {} warning(s)", path, errors, warnings);
+    }
+    errors == 0 && (!strict || warnings == 0)
+}
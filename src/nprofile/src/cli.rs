@@ -1,58 +1,19 @@
 use std::collections::HashMap;
 
-use libnprofile::profile::{Profile, ProfileConfig};
-
-/// Default profile environment.
-const DEFAULT_ENVIRONMENT: &str = if cfg!(target_os = "linux") {
-    "linux"
-} else if cfg!(target_os = "windows") {
-    "windows"
-} else if cfg!(target_os = "macos") {
-    "macos"
-} else {
-    panic!("Cannot determine default environment for system");
+use libnprofile::{
+    plan::{Executor, Plan, ProfileAction, Step},
+    profile::{CommandPhase, ExecutionOptions, Interpolation, ProfileConfig},
+    validation::Severity,
 };
 
-/// Supported profile actions.
-#[derive(Clone, Debug, Default, clap::ValueEnum)]
-pub(crate) enum ProfileAction {
-    /// Disable the profile.
-    #[value(alias = "d")]
-    Disable,
-    /// Enable the profile.
+/// Output formats for commands that report on profiles.
+#[derive(Clone, Copy, Debug, Default, clap::ValueEnum)]
+pub(crate) enum OutputFormat {
+    /// Human-readable text.
     #[default]
-    #[value(aliases = &["u", "e"])]
-    Enable,
-    /// Reset the profile (disable then re-enable).
-    #[value(alias = "r")]
-    Reset,
-}
-
-impl From<&ProfileAction> for String {
-    fn from(value: &ProfileAction) -> Self {
-        match value {
-            ProfileAction::Disable => "disable".to_string(),
-            ProfileAction::Enable => "enable".to_string(),
-            ProfileAction::Reset => "reset".to_string(),
-        }
-    }
-}
-
-/// Internal profile actions.
-///
-/// User-facing profile actions are sequences of one or more core actions.
-#[derive(Debug)]
-enum CoreProfileAction {
-    /// Disable the profile.
-    Disable,
-    /// Enable the profile.
-    Enable,
-}
-
-impl std::fmt::Display for ProfileAction {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}", String::from(self))
-    }
+    Text,
+    /// JSON, for scripting.
+    Json,
 }
 
 fn parse_key_value_pair<K, V>(arg: &str) -> Result<(K, V), Box<dyn std::error::Error + Send + Sync + 'static>>
@@ -78,118 +39,225 @@ where
         .collect::<Result<HashMap<K, V>, Box<dyn std::error::Error + Send + Sync + 'static>>>()
 }
 
+fn parse_seconds(arg: &str) -> Result<std::time::Duration, Box<dyn std::error::Error + Send + Sync + 'static>> {
+    Ok(std::time::Duration::try_from_secs_f64(arg.parse()?)?)
+}
+
+/// Arguments for running a profile action.
+#[derive(Debug, clap::Args)]
+pub(crate) struct ProfileActionArgs {
+    /// Name or alias of the profile
+    pub profile_name: String,
+    /// Profile-specific args formatted as comma-separated key-value pairs (e.g. ssid=MyWiFi,device=radio1),
+    /// missing args are prompted for when run from a terminal
+    #[arg(value_parser = parse_key_value_pairs::<String, String>)]
+    pub profile_args: Option<HashMap<String, String>>,
+    /// Print the commands that would be run, in order, without running them
+    #[arg(long, action = clap::ArgAction::SetTrue)]
+    pub dry_run: bool,
+    /// Leave profiles enabled by earlier steps as-is when a later step fails
+    #[arg(long, action = clap::ArgAction::SetTrue)]
+    pub no_rollback: bool,
+}
+
+/// Commands for inspecting the config.
+#[derive(Debug, clap::Subcommand)]
+pub(crate) enum ConfigCommand {
+    /// Print the locations config files are searched at, in order, and whether each is loaded.
+    Path,
+}
+
+/// Supported commands.
+#[derive(Debug, clap::Subcommand)]
+pub(crate) enum Command {
+    /// Enable a profile and its dependencies.
+    #[command(visible_aliases = &["u", "e"])]
+    Enable(ProfileActionArgs),
+    /// Disable a profile and its dependencies.
+    #[command(visible_alias = "d")]
+    Disable(ProfileActionArgs),
+    /// Reset a profile and its dependencies (disable then re-enable).
+    #[command(visible_alias = "r")]
+    Reset(ProfileActionArgs),
+    /// Validate the config file and report every error and warning without running any commands.
+    #[command(visible_alias = "lint")]
+    Check {
+        /// Treat warnings as errors
+        #[arg(long, action = clap::ArgAction::SetTrue)]
+        strict: bool,
+    },
+    /// Inspect the config files.
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommand,
+    },
+    /// List profiles with their aliases, environments and dependencies.
+    #[command(visible_alias = "ls")]
+    List,
+    /// Show the parameters and commands of a profile.
+    Show {
+        /// Name or alias of the profile
+        profile_name: String,
+    },
+    /// Report whether profiles are enabled, using their is_enabled commands.
+    #[command(visible_alias = "st")]
+    Status {
+        /// Name or alias of the profile (defaults to all profiles)
+        profile_name: Option<String>,
+        /// Profile-specific args formatted as comma-separated key-value pairs (e.g. ssid=MyWiFi,device=radio1)
+        #[arg(value_parser = parse_key_value_pairs::<String, String>)]
+        profile_args: Option<HashMap<String, String>>,
+    },
+}
+
+/// Cross-platform network profile manager.
 #[derive(Debug, clap::Parser)]
 pub(crate) struct Cli {
-    /// Path to the profile config file.
-    #[arg(required = true, short, long, env = "CONFIG_PATH", value_hint = clap::ValueHint::FilePath)]
-    pub config_path: std::path::PathBuf,
+    /// Path to the profile config file (defaults to the system config overlaid with the user config,
+    /// see `config path`)
+    #[arg(short, long, env = "CONFIG_PATH", value_hint = clap::ValueHint::FilePath)]
+    pub config_path: Option<std::path::PathBuf>,
     /// Enable debug logging
-    #[arg(short, long, action = clap::ArgAction::SetTrue)]
+    #[arg(short, long, global = true, action = clap::ArgAction::SetTrue)]
     pub debug: bool,
     /// Name of the environment to use for managing the profile
-    #[arg(short, long, env = "ENVIRONMENT_NAME", default_value = DEFAULT_ENVIRONMENT)]
-    pub environment_name: String,
-    /// Name of the profile
-    pub profile_name: String,
-    /// Profile action
-    #[arg(default_value_t)]
-    pub action: ProfileAction,
-    /// Profile-specific args formatted as comma-separated key-value pairs (e.g. ssid=MyWiFi,device=radio1)
-    #[arg(value_parser = parse_key_value_pairs::<String, String>)]
-    pub profile_args: Option<HashMap<String, String>>,
+    /// (defaults to the environment detected for each profile, or the one named after the operating system)
+    #[arg(short, long, global = true, env = "ENVIRONMENT_NAME")]
+    pub environment_name: Option<String>,
+    /// Default maximum time (in seconds) each profile command can run for, unless set in the config
+    #[arg(short, long, global = true, env = "NPROFILE_TIMEOUT", value_parser = parse_seconds)]
+    pub timeout: Option<std::time::Duration>,
+    /// Inject profile args into commands as-is instead of quoting them, unless set in the config (unsafe)
+    #[arg(long, global = true, action = clap::ArgAction::SetTrue)]
+    pub raw_parameters: bool,
+    /// Log the output of profile commands line-by-line while they run
+    #[arg(long, global = true, action = clap::ArgAction::SetTrue)]
+    pub verbose_commands: bool,
+    /// Output format for reports (enable, disable and reset report every command that was run)
+    #[arg(short, long, global = true, value_enum, default_value_t)]
+    pub output: OutputFormat,
+    #[command(subcommand)]
+    pub command: Command,
 }
 
 impl Cli {
-    fn validate_args(&self) {
-        if !self.config_path.is_file() {
-            log::error!("Config path is either not a file or does not exist");
-            std::process::exit(1);
-        }
-    }
-
-    fn read_config_from_file(&self) -> ProfileConfig {
-        let config = std::fs::read_to_string(&self.config_path).unwrap_or_else(|err| {
-            log::error!("Failed to read config file: {}", err.to_string());
-            std::process::exit(1);
-        });
-        let config: ProfileConfig = toml::from_str(config.as_str()).unwrap_or_else(|err| {
-            log::error!("Failed to read config file: {}", err.to_string());
+    fn read_config_from_file(config_paths: &[std::path::PathBuf]) -> ProfileConfig {
+        let config = ProfileConfig::load_layers(config_paths).unwrap_or_else(|err| {
+            log::error!("{}", err.to_string());
             std::process::exit(1);
         });
-        if let Err(err) = config.is_valid() {
-            log::error!("{}", err.to_string());
+        let mut is_valid = true;
+        for issue in config.validate() {
+            match issue.severity() {
+                Severity::Error => {
+                    log::error!("{}", issue);
+                    is_valid = false;
+                },
+                Severity::Warning => log::warn!("{}", issue),
+            }
+        }
+        if !is_valid {
             std::process::exit(1);
         }
-        log::debug!(
-            "Loaded profile config from {:?}, contains {:02} profiles",
-            self.config_path,
-            config.profiles.len(),
-        );
+        log::debug!("Loaded profile config from {:?}, contains {:02} profiles", config_paths, config.profiles.len(),);
         config
     }
 
-    fn transform_to_profile_map(config: &ProfileConfig) -> HashMap<&str, &Profile> {
-        let mut profile_map = HashMap::<&str, &Profile>::with_capacity(config.profiles.len());
-        for profile in config.profiles.iter() {
-            profile_map.insert(&profile.name, profile);
-            if let Some(aliases) = profile.aliases.as_ref() {
-                for alias in aliases.iter() {
-                    profile_map.insert(alias, profile);
-                }
-            }
+    fn execution_options(&self) -> ExecutionOptions {
+        ExecutionOptions {
+            timeout: self.timeout,
+            interpolation: self.raw_parameters.then_some(Interpolation::Raw),
+            on_output: self.verbose_commands.then(libnprofile::process::OutputCallback::log),
+            secrets: Some(libnprofile::parameter::SecretCache::new()),
+            ..Default::default()
         }
-        profile_map
     }
 
-    fn get_profiles_to_action<'a: 'b, 'b>(
-        &'a self,
-        profile_map: &'b HashMap<&'b str, &'b Profile>,
-        config: &'b ProfileConfig,
-    ) -> Vec<(&'b Profile, Option<&'b str>)> {
-        if let Some(profile) = profile_map.get(self.profile_name.as_str()) {
-            let mut profiles = Vec::with_capacity(profile.dependencies.as_ref().map(Vec::len).unwrap_or(0) + 1);
-            if let Some(dependencies) = profile.dependencies.as_ref() {
-                for dependency in dependencies {
-                    if let Some(dependency_profile) = profile_map.get(dependency.name.as_str()) {
-                        profiles.push((*dependency_profile, dependency.env_name.as_deref()));
-                    } else {
-                        log::error!("Invalid dependency profile name {}", dependency.name);
-                        std::process::exit(1);
-                    }
-                }
-            }
-            if !profile.is_composition_profile() {
-                profiles.push((profile, None));
-            }
-            profiles
-        } else {
-            log::error!(
-                "invalid profile name {}, possible values are: {}",
-                self.profile_name,
-                config.profiles.iter().map(|profile| profile.name.as_str()).collect::<Vec<_>>().join(", "),
-            );
+    fn exit_with_invalid_profile_name(config: &ProfileConfig, profile_name: &str) -> ! {
+        log::error!(
+            "invalid profile name {}, possible values are: {}",
+            profile_name,
+            config.profiles.iter().map(|profile| profile.name.as_str()).collect::<Vec<_>>().join(", "),
+        );
+        std::process::exit(1);
+    }
+
+    fn dry_run_step(&self, plan: &Plan<'_>, step: &Step<'_>) {
+        let (profile, environment_name) = (step.profile, step.environment_name);
+        let commands = step.prepare(plan.args(), &self.execution_options()).unwrap_or_else(|err| {
+            log::error!("Failed to prepare profile commands: {}", err);
             std::process::exit(1);
+        });
+
+        println!("# {} profile {} using environment {}", step.action, profile.name, environment_name);
+        if commands.is_empty() {
+            println!("(nothing to run, is_enabled is not defined)");
+        }
+        let has_is_enabled = commands.iter().any(|command| command.phase == CommandPhase::IsEnabled);
+        for command in commands {
+            let condition = match command.phase {
+                CommandPhase::Enable if has_is_enabled => " (if not enabled)",
+                CommandPhase::Disable => " (if enabled)",
+                CommandPhase::WaitUntil => " (polled until it succeeds)",
+                CommandPhase::Verify => " (retried until it succeeds)",
+                _ => "",
+            };
+            println!("{}{} [{}]:", command.phase, condition, command.shell);
+            for line in command.command.lines() {
+                println!("    {}", line);
+            }
         }
     }
 
-    fn run_profile_action(&self, profile: &Profile, environment_name: Option<&str>, action: CoreProfileAction) {
-        let environment_name = environment_name.unwrap_or(self.environment_name.as_str());
-        match action {
-            CoreProfileAction::Enable => {
-                log::info!("Enabling profile {} using environment {}", profile.name, self.environment_name);
-                if let Err(err) = profile.enable(environment_name, self.profile_args.as_ref()) {
-                    log::error!("Failed to enable profile: {}", err);
-                    std::process::exit(1);
+    fn run_action(&self, config_paths: &[std::path::PathBuf], action: ProfileAction, args: &ProfileActionArgs) {
+        let config = Self::read_config_from_file(config_paths);
+        let mut profile_args = args.profile_args.clone().unwrap_or_default();
+        crate::prompt::prompt_for_missing_args(
+            &config,
+            args.profile_name.as_str(),
+            self.environment_name.as_deref(),
+            &mut profile_args,
+        );
+        let plan = Plan::new(
+            &config,
+            args.profile_name.as_str(),
+            action,
+            self.environment_name.as_deref(),
+            Some(&profile_args),
+        )
+        .unwrap_or_else(|err| match err {
+            libnprofile::error::Error::ProfileNotFound { .. } => {
+                Self::exit_with_invalid_profile_name(&config, args.profile_name.as_str())
+            },
+            _ => {
+                log::error!("{}", err.to_string());
+                std::process::exit(1);
+            },
+        });
+
+        if args.dry_run {
+            plan.steps().iter().for_each(|step| self.dry_run_step(&plan, step));
+            return;
+        }
+
+        let executor = Executor { rollback: !args.no_rollback, ..Executor::new(self.execution_options()) };
+        match executor.execute(&plan) {
+            Ok(report) => {
+                if let OutputFormat::Json = self.output {
+                    crate::inspect::print_json(&report);
                 }
-                log::info!("Enabled profile {}", profile.name);
             },
-            CoreProfileAction::Disable => {
-                log::info!("Disabling profile {} using environment {}", profile.name, self.environment_name);
-                if let Err(err) = profile.disable(environment_name, self.profile_args.as_ref()) {
-                    log::error!("Failed to disable profile: {}", err);
-                    std::process::exit(1);
+            Err(err) => {
+                log::error!("{}", err);
+                if let libnprofile::error::Error::StepFailed { rolled_back, report, .. } = &err {
+                    if !rolled_back.is_empty() {
+                        log::error!("Rolled back profiles: {}", rolled_back.join(", "));
+                    }
+                    if let OutputFormat::Json = self.output {
+                        crate::inspect::print_json(report);
+                    }
                 }
-                log::info!("Disabled profile {}", profile.name);
+                std::process::exit(1);
             },
         }
     }
@@ -197,29 +265,42 @@ impl Cli {
     pub fn run(self) {
         crate::logging::configure_logging(self.debug);
 
-        self.validate_args();
-        let config = self.read_config_from_file();
-        let profile_map = Self::transform_to_profile_map(&config);
-        let profiles = self.get_profiles_to_action(&profile_map, &config);
-
-        match self.action {
-            ProfileAction::Disable => {
-                for (profile, environment_name) in profiles.into_iter().rev() {
-                    self.run_profile_action(profile, environment_name, CoreProfileAction::Disable);
+        let config_paths = || crate::config::resolve_config_paths(self.config_path.as_deref());
+        match &self.command {
+            Command::Enable(args) => self.run_action(&config_paths(), ProfileAction::Enable, args),
+            Command::Disable(args) => self.run_action(&config_paths(), ProfileAction::Disable, args),
+            Command::Reset(args) => self.run_action(&config_paths(), ProfileAction::Reset, args),
+            Command::Check { strict } => {
+                if !crate::check::check_config(&config_paths(), *strict) {
+                    std::process::exit(1);
                 }
             },
-            ProfileAction::Enable => {
-                for (profile, environment_name) in profiles {
-                    self.run_profile_action(profile, environment_name, CoreProfileAction::Enable);
-                }
+            Command::Config { command: ConfigCommand::Path } => {
+                crate::config::print_config_locations(self.config_path.as_deref(), self.output)
             },
-            ProfileAction::Reset => {
-                for (profile, environment_name) in profiles.iter().rev() {
-                    self.run_profile_action(*profile, *environment_name, CoreProfileAction::Disable);
-                }
-                for (profile, environment_name) in profiles {
-                    self.run_profile_action(profile, environment_name, CoreProfileAction::Enable);
-                }
+            Command::List => crate::inspect::list_profiles(&Self::read_config_from_file(&config_paths()), self.output),
+            Command::Show { profile_name } => {
+                let config = Self::read_config_from_file(&config_paths());
+                let profile = config
+                    .get_profile(profile_name)
+                    .unwrap_or_else(|| Self::exit_with_invalid_profile_name(&config, profile_name));
+                crate::inspect::show_profile(profile, self.output);
+            },
+            Command::Status { profile_name, profile_args } => {
+                let config = Self::read_config_from_file(&config_paths());
+                let profile = profile_name.as_ref().map(|profile_name| {
+                    config
+                        .get_profile(profile_name)
+                        .unwrap_or_else(|| Self::exit_with_invalid_profile_name(&config, profile_name))
+                });
+                crate::status::report_status(
+                    &config,
+                    profile,
+                    self.environment_name.as_deref(),
+                    profile_args.as_ref(),
+                    &self.execution_options(),
+                    self.output,
+                );
             },
         }
     }
@@ -0,0 +1,190 @@
+use std::path::{Path, PathBuf};
+
+use crate::cli::OutputFormat;
+
+/// Name of the config file in config directories.
+const CONFIG_FILE_NAME: &str = "config.toml";
+/// Environment variable with the path of the user config, searched before the default locations.
+const CONFIG_ENV_VAR: &str = "NPROFILE_CONFIG";
+/// Directory of the system config (only on Unix-like systems).
+const SYSTEM_CONFIG_DIRECTORY: &str = "/etc/nprofile";
+
+/// Layers of the config, from the lowest priority to the highest.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+enum ConfigLayer {
+    /// Config shared by all users.
+    System,
+    /// Config of the current user, whose profiles override system profiles with the same name.
+    User,
+}
+
+impl std::fmt::Display for ConfigLayer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConfigLayer::System => write!(f, "system"),
+            ConfigLayer::User => write!(f, "user"),
+        }
+    }
+}
+
+/// Whether a config location is loaded.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+enum ConfigStatus {
+    /// The config file exists and is loaded.
+    Loaded,
+    /// The config file exists, but a location searched before it is loaded instead.
+    Skipped,
+    /// The config file does not exist.
+    Missing,
+}
+
+impl std::fmt::Display for ConfigStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConfigStatus::Loaded => write!(f, "loaded"),
+            ConfigStatus::Skipped => write!(f, "skipped"),
+            ConfigStatus::Missing => write!(f, "missing"),
+        }
+    }
+}
+
+/// Location a config file is searched at.
+#[derive(Clone, Debug, serde::Serialize)]
+struct ConfigLocation {
+    layer: ConfigLayer,
+    path: PathBuf,
+    /// Option, environment variable or default location the path comes from.
+    source: &'static str,
+    status: ConfigStatus,
+    /// Whether the path was set by the user, so it must exist.
+    #[serde(skip)]
+    explicit: bool,
+}
+
+fn home_directory() -> Option<PathBuf> {
+    std::env::var_os(if cfg!(windows) { "USERPROFILE" } else { "HOME" })
+        .filter(|home| !home.is_empty())
+        .map(PathBuf::from)
+}
+
+/// Get the config file at a path, which is either the file or a directory that contains it.
+fn config_file(path: &Path) -> PathBuf {
+    if path.is_dir() {
+        path.join(CONFIG_FILE_NAME)
+    } else {
+        path.to_owned()
+    }
+}
+
+fn is_same_file(a: &Path, b: &Path) -> bool {
+    match (std::fs::canonicalize(a), std::fs::canonicalize(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => a == b,
+    }
+}
+
+/// Get the locations config files are searched at, in search order, and whether each is loaded.
+///
+/// A config path set with `--config-path` (or `CONFIG_PATH`) is the only location. Otherwise the user config is the
+/// first of `$NPROFILE_CONFIG`, `$XDG_CONFIG_HOME/nprofile/config.toml` and `~/.config/nprofile/config.toml` that
+/// exists (only `$NPROFILE_CONFIG` is searched if it is set), and the system config is `/etc/nprofile/config.toml`.
+/// `$XDG_CONFIG_HOME` is ignored if it is not an absolute path, as per the XDG Base Directory Specification.
+/// Config paths that are directories resolve to the `config.toml` file in them.
+fn search_config_locations(config_path: Option<&Path>) -> Vec<ConfigLocation> {
+    let location = |layer, path: PathBuf, source, explicit| {
+        let status = if path.is_file() { ConfigStatus::Skipped } else { ConfigStatus::Missing };
+        ConfigLocation { layer, path, source, status, explicit }
+    };
+    if let Some(path) = config_path {
+        let mut location = location(ConfigLayer::User, config_file(path), "--config-path", true);
+        if location.status == ConfigStatus::Skipped {
+            location.status = ConfigStatus::Loaded;
+        }
+        return vec![location];
+    }
+
+    let mut locations = Vec::new();
+    if let Some(path) = std::env::var_os(CONFIG_ENV_VAR).filter(|path| !path.is_empty()) {
+        locations.push(location(ConfigLayer::User, config_file(Path::new(&path)), "$NPROFILE_CONFIG", true));
+    }
+    if let Some(directory) = std::env::var_os("XDG_CONFIG_HOME").map(PathBuf::from).filter(|path| path.is_absolute()) {
+        let path = directory.join("nprofile").join(CONFIG_FILE_NAME);
+        locations.push(location(ConfigLayer::User, path, "$XDG_CONFIG_HOME", false));
+    }
+    if let Some(home) = home_directory() {
+        let path = home.join(".config").join("nprofile").join(CONFIG_FILE_NAME);
+        locations.push(location(ConfigLayer::User, path, "~/.config", false));
+    }
+    if cfg!(unix) {
+        let path = Path::new(SYSTEM_CONFIG_DIRECTORY).join(CONFIG_FILE_NAME);
+        locations.push(location(ConfigLayer::System, path, SYSTEM_CONFIG_DIRECTORY, false));
+    }
+
+    let user = match locations.first() {
+        Some(location) if location.explicit => Some(0),
+        _ => locations
+            .iter()
+            .position(|location| location.layer == ConfigLayer::User && location.status != ConfigStatus::Missing),
+    };
+    if let Some(user) = user.filter(|user| locations[*user].status == ConfigStatus::Skipped) {
+        locations[user].status = ConfigStatus::Loaded;
+    }
+    // The user config is only loaded once if it is also the system config
+    let user_path =
+        user.filter(|user| locations[*user].status == ConfigStatus::Loaded).map(|user| locations[user].path.clone());
+    if let Some(system) = locations.iter_mut().find(|location| location.layer == ConfigLayer::System) {
+        if system.status == ConfigStatus::Skipped && user_path.is_none_or(|path| !is_same_file(&path, &system.path)) {
+            system.status = ConfigStatus::Loaded;
+        }
+    }
+    locations
+}
+
+/// Resolve the config files to load, from the lowest priority (system) to the highest (user).
+///
+/// See: [`search_config_locations`]. Exits if the config path (or `$NPROFILE_CONFIG`) does not exist,
+/// or if no config file is found.
+pub(crate) fn resolve_config_paths(config_path: Option<&Path>) -> Vec<PathBuf> {
+    let locations = search_config_locations(config_path);
+    if let Some(location) =
+        locations.iter().find(|location| location.explicit && location.status == ConfigStatus::Missing)
+    {
+        log::error!(
+            "Config path {} (set by {}) is either not a file or does not exist",
+            location.path.display(),
+            location.source,
+        );
+        std::process::exit(1);
+    }
+
+    let loaded = locations.iter().rev().filter(|location| location.status == ConfigStatus::Loaded).collect::<Vec<_>>();
+    if loaded.is_empty() {
+        log::error!(
+            "No config file found, set --config-path or {}, or create one of: {}",
+            CONFIG_ENV_VAR,
+            locations.iter().map(|location| location.path.display().to_string()).collect::<Vec<_>>().join(", "),
+        );
+        std::process::exit(1);
+    }
+    for location in loaded.iter() {
+        log::debug!("Resolved {} config {}", location.layer, location.path.display());
+    }
+    loaded.into_iter().map(|location| location.path.to_owned()).collect()
+}
+
+/// Print the locations config files are searched at, in search order, and whether each is loaded.
+///
+/// Works without any config file, to diagnose why a config is not found.
+pub(crate) fn print_config_locations(config_path: Option<&Path>, output: OutputFormat) {
+    let locations = search_config_locations(config_path);
+    match output {
+        OutputFormat::Text => {
+            for location in locations.iter() {
+                println!("{}\t{}\t{} ({})", location.layer, location.status, location.path.display(), location.source);
+            }
+        },
+        OutputFormat::Json => crate::inspect::print_json(&locations),
+    }
+}
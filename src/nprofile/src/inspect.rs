@@ -0,0 +1,336 @@
+use std::{collections::BTreeMap, time::Duration};
+
+use libnprofile::{
+    parameter::{Parameter, ParameterType, SecretSource},
+    profile::{Profile, ProfileConfig},
+};
+
+use crate::cli::OutputFormat;
+
+/// Node in a profile's dependency tree.
+#[derive(Debug, serde::Serialize)]
+struct DependencyNode<'a> {
+    name: &'a str,
+    environment: Option<&'a str>,
+    composition: bool,
+    dependencies: Vec<DependencyNode<'a>>,
+}
+
+impl<'a> DependencyNode<'a> {
+    /// Build the dependency tree of a profile.
+    ///
+    /// Assumes all dependencies are defined. Dependencies that form a cycle are listed, but not expanded again,
+    /// so the tree of an invalid config can still be built.
+    fn tree(config: &'a ProfileConfig, profile: &'a Profile) -> Vec<Self> {
+        Self::subtree(config, profile, &mut vec![profile.name.as_str()])
+    }
+
+    fn subtree(config: &'a ProfileConfig, profile: &'a Profile, path: &mut Vec<&'a str>) -> Vec<Self> {
+        profile
+            .dependencies
+            .iter()
+            .flatten()
+            .filter_map(|dependency| {
+                config.get_profile(&dependency.name).map(|dependency_profile| {
+                    let name = dependency_profile.name.as_str();
+                    let dependencies = if path.contains(&name) {
+                        Vec::new()
+                    } else {
+                        path.push(name);
+                        let dependencies = Self::subtree(config, dependency_profile, path);
+                        path.pop();
+                        dependencies
+                    };
+                    DependencyNode {
+                        name,
+                        environment: dependency.env_name.as_deref(),
+                        composition: dependency_profile.is_composition_profile(),
+                        dependencies,
+                    }
+                })
+            })
+            .collect()
+    }
+
+    fn print(&self, prefix: &str, is_last: bool) {
+        let (branch, indent) = if is_last { ("└── ", "    ") } else { ("├── ", "│   ") };
+        let environment = self.environment.map(|environment| format!(":{}", environment)).unwrap_or_default();
+        let composition = if self.composition { " [composition]" } else { "" };
+        println!("{}{}{}{}{}", prefix, branch, self.name, environment, composition);
+        let prefix = format!("{}{}", prefix, indent);
+        for (index, dependency) in self.dependencies.iter().enumerate() {
+            dependency.print(&prefix, index + 1 == self.dependencies.len());
+        }
+    }
+}
+
+/// Summary of a profile, for listing.
+#[derive(Debug, serde::Serialize)]
+struct ProfileSummary<'a> {
+    name: &'a str,
+    aliases: Vec<&'a str>,
+    environments: Vec<&'a str>,
+    composition: bool,
+    source: Option<&'a std::path::Path>,
+    dependencies: Vec<DependencyNode<'a>>,
+}
+
+impl<'a> ProfileSummary<'a> {
+    fn new(config: &'a ProfileConfig, profile: &'a Profile) -> Self {
+        let mut environments = profile.envs.iter().flatten().map(|(name, _)| name.as_str()).collect::<Vec<_>>();
+        environments.sort();
+        ProfileSummary {
+            name: profile.name.as_str(),
+            aliases: profile.aliases.iter().flatten().map(String::as_str).collect(),
+            environments,
+            composition: profile.is_composition_profile(),
+            source: profile.source.as_deref(),
+            dependencies: DependencyNode::tree(config, profile),
+        }
+    }
+
+    fn print(&self) {
+        let mut line = self.name.to_owned();
+        if !self.aliases.is_empty() {
+            line.push_str(&format!(" (aliases: {})", self.aliases.join(", ")));
+        }
+        if self.composition {
+            line.push_str(" [composition]");
+        } else {
+            line.push_str(&format!(" [envs: {}]", self.environments.join(", ")));
+        }
+        println!("{}", line);
+        for (index, dependency) in self.dependencies.iter().enumerate() {
+            dependency.print("", index + 1 == self.dependencies.len());
+        }
+    }
+}
+
+/// Command of a profile environment, for showing.
+#[derive(Debug, serde::Serialize)]
+struct CommandDetails<'a> {
+    name: &'static str,
+    command: &'a str,
+    timeout: Option<f64>,
+    interactive: bool,
+}
+
+/// Parameter of a profile environment, for showing.
+#[derive(Debug, serde::Serialize)]
+struct ParameterDetails<'a> {
+    #[serde(rename = "type")]
+    kind: ParameterType,
+    required: bool,
+    default: Option<&'a str>,
+    #[serde(flatten)]
+    source: Option<&'a SecretSource>,
+    description: Option<&'a str>,
+    pattern: Option<&'a str>,
+    values: Option<&'a [String]>,
+    secret: bool,
+}
+
+impl<'a> ParameterDetails<'a> {
+    fn new(parameter: &'a Parameter) -> Self {
+        ParameterDetails {
+            kind: parameter.kind,
+            required: parameter.required,
+            default: parameter.default.as_deref().map(|default| {
+                if parameter.is_secret() {
+                    libnprofile::redact::REDACTED
+                } else {
+                    default
+                }
+            }),
+            source: parameter.source.as_ref(),
+            description: parameter.description.as_deref(),
+            pattern: parameter.pattern(),
+            values: parameter.values.as_deref(),
+            secret: parameter.is_secret(),
+        }
+    }
+
+    fn print(&self, name: &str) {
+        let mut line = match self.default {
+            Some(default) if !self.required => format!("    {} = {:?}", name, default),
+            _ => format!("    {}", name),
+        };
+        let mut options = vec![self.kind.to_string()];
+        if self.required {
+            options.push("required".to_owned());
+        }
+        if self.secret {
+            options.push("secret".to_owned());
+        }
+        if let Some(source) = self.source {
+            options.push(source.to_string());
+        }
+        if let Some(values) = self.values {
+            options.push(format!("one of: {}", values.join(", ")));
+        }
+        if let Some(pattern) = self.pattern {
+            options.push(format!("pattern: {}", pattern));
+        }
+        line.push_str(&format!(" ({})", options.join(", ")));
+        if let Some(description) = self.description {
+            line.push_str(&format!(": {}", description));
+        }
+        println!("{}", line);
+    }
+}
+
+/// Profile environment, for showing.
+#[derive(Debug, serde::Serialize)]
+struct EnvironmentDetails<'a> {
+    name: &'a str,
+    extends: Option<&'a str>,
+    shell: &'a str,
+    timeout: Option<f64>,
+    priority: i32,
+    detect: Option<String>,
+    parameters: BTreeMap<&'a str, ParameterDetails<'a>>,
+    commands: Vec<CommandDetails<'a>>,
+}
+
+/// Profile, for showing.
+#[derive(Debug, serde::Serialize)]
+struct ProfileDetails<'a> {
+    name: &'a str,
+    aliases: Vec<&'a str>,
+    extends: Option<&'a str>,
+    composition: bool,
+    source: Option<&'a std::path::Path>,
+    dependencies: Vec<String>,
+    environments: Vec<EnvironmentDetails<'a>>,
+}
+
+impl<'a> ProfileDetails<'a> {
+    fn new(profile: &'a Profile) -> Self {
+        let mut environments = profile
+            .envs
+            .iter()
+            .flatten()
+            .map(|(name, environment)| EnvironmentDetails {
+                name: name.as_str(),
+                extends: environment.extends.as_deref(),
+                shell: environment.shell.as_deref().unwrap_or(libnprofile::process::DEFAULT_SHELL),
+                timeout: environment.timeout.as_ref().map(Duration::as_secs_f64),
+                priority: environment.priority,
+                detect: environment.detect.as_ref().map(ToString::to_string),
+                parameters: environment
+                    .parameters
+                    .iter()
+                    .flatten()
+                    .map(|(name, parameter)| (name.as_str(), ParameterDetails::new(parameter)))
+                    .collect(),
+                commands: environment
+                    .commands()
+                    .map(|(name, command)| CommandDetails {
+                        name,
+                        command: command.as_str(),
+                        timeout: command.timeout().as_ref().map(Duration::as_secs_f64),
+                        interactive: command.interactive(),
+                    })
+                    .collect(),
+            })
+            .collect::<Vec<_>>();
+        environments.sort_by_key(|environment| environment.name);
+        ProfileDetails {
+            name: profile.name.as_str(),
+            aliases: profile.aliases.iter().flatten().map(String::as_str).collect(),
+            extends: profile.extends.as_deref(),
+            composition: profile.is_composition_profile(),
+            source: profile.source.as_deref(),
+            dependencies: profile.dependencies.iter().flatten().map(ToString::to_string).collect(),
+            environments,
+        }
+    }
+
+    fn print(&self) {
+        println!("Profile: {}", self.name);
+        if !self.aliases.is_empty() {
+            println!("Aliases: {}", self.aliases.join(", "));
+        }
+        if let Some(source) = self.source {
+            println!("Source: {}", source.display());
+        }
+        if let Some(extends) = self.extends {
+            println!("Extends: {}", extends);
+        }
+        if !self.dependencies.is_empty() {
+            println!("Dependencies: {}", self.dependencies.join(", "));
+        }
+        if self.composition {
+            println!("Composition profile (no environments)");
+        }
+        for environment in self.environments.iter() {
+            println!();
+            let mut options = vec![format!("shell: {}", environment.shell)];
+            if let Some(extends) = environment.extends {
+                options.push(format!("extends: {}", extends));
+            }
+            if let Some(timeout) = environment.timeout {
+                options.push(format!("timeout: {}s", timeout));
+            }
+            if environment.priority != 0 {
+                options.push(format!("priority: {}", environment.priority));
+            }
+            if let Some(detect) = environment.detect.as_ref() {
+                options.push(format!("detect: {}", detect));
+            }
+            println!("Environment {} ({})", environment.name, options.join(", "));
+            if !environment.parameters.is_empty() {
+                println!("  Parameters:");
+                for (name, parameter) in environment.parameters.iter() {
+                    parameter.print(name);
+                }
+            }
+            for command in environment.commands.iter() {
+                let mut options = Vec::new();
+                if let Some(timeout) = command.timeout {
+                    options.push(format!("timeout: {}s", timeout));
+                }
+                if command.interactive {
+                    options.push("interactive".to_owned());
+                }
+                if options.is_empty() {
+                    println!("  {}:", command.name);
+                } else {
+                    println!("  {} ({}):", command.name, options.join(", "));
+                }
+                for line in command.command.lines() {
+                    println!("    {}", line);
+                }
+            }
+        }
+    }
+}
+
+/// Print a value as pretty-printed JSON.
+pub(crate) fn print_json<T: serde::Serialize>(value: &T) {
+    match serde_json::to_string_pretty(value) {
+        Ok(json) => println!("{}", json),
+        Err(err) => {
+            log::error!("Failed to serialize output: {}", err);
+            std::process::exit(1);
+        },
+    }
+}
+
+/// List all profiles in the config.
+pub(crate) fn list_profiles(config: &ProfileConfig, output: OutputFormat) {
+    let profiles = config.profiles.iter().map(|profile| ProfileSummary::new(config, profile)).collect::<Vec<_>>();
+    match output {
+        OutputFormat::Text => profiles.iter().for_each(ProfileSummary::print),
+        OutputFormat::Json => print_json(&profiles),
+    }
+}
+
+/// Show the details of a single profile.
+pub(crate) fn show_profile(profile: &Profile, output: OutputFormat) {
+    let details = ProfileDetails::new(profile);
+    match output {
+        OutputFormat::Text => details.print(),
+        OutputFormat::Json => print_json(&details),
+    }
+}
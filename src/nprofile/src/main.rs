@@ -1,7 +1,12 @@
 use clap::Parser;
 
+mod check;
 mod cli;
+mod config;
+mod inspect;
 mod logging;
+mod prompt;
+mod status;
 
 fn main() {
     cli::Cli::parse().run()
@@ -0,0 +1,86 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    io::{BufRead, IsTerminal, Write},
+};
+
+use libnprofile::{parameter::Parameter, profile::ProfileConfig};
+
+/// Parameters of a profile and its dependencies that need args which were not provided, by name.
+///
+/// Parameters shared by several profiles are only included once.
+fn missing_parameters<'a>(
+    config: &'a ProfileConfig,
+    profile_name: &str,
+    environment_name: Option<&'a str>,
+    args: &HashMap<String, String>,
+) -> BTreeMap<&'a str, &'a Parameter> {
+    let mut missing = BTreeMap::new();
+    // Invalid profiles and environments are reported when the action is planned
+    for (profile, dependency_environment_name) in config.resolve_dependencies(profile_name).unwrap_or_default() {
+        let environment_name = profile.choose_environment(dependency_environment_name.or(environment_name));
+        let parameters = profile
+            .envs
+            .as_ref()
+            .and_then(|envs| envs.get(environment_name))
+            .and_then(|environment| environment.parameters.as_ref());
+        for (name, parameter) in parameters.into_iter().flatten() {
+            if parameter.needs_arg() && !args.contains_key(name) {
+                missing.entry(name.as_str()).or_insert(parameter);
+            }
+        }
+    }
+    missing
+}
+
+/// Read a value for a parameter from the terminal, without echoing it if the parameter is secret.
+fn read_value(name: &str, parameter: &Parameter) -> std::io::Result<String> {
+    let prompt = match parameter.description.as_deref() {
+        Some(description) => format!("{} ({}): ", name, description),
+        None => format!("{}: ", name),
+    };
+    if parameter.is_secret() {
+        return rpassword::prompt_password(prompt);
+    }
+
+    let mut stderr = std::io::stderr().lock();
+    stderr.write_all(prompt.as_bytes())?;
+    stderr.flush()?;
+    let mut value = String::new();
+    std::io::stdin().lock().read_line(&mut value)?;
+    Ok(value.trim_end_matches(['\r', '\n']).to_owned())
+}
+
+/// Prompt for args of parameters that are required (or have no default) and were not provided.
+///
+/// Values are asked for again until they match their parameter. Parameters left empty are skipped,
+/// and reported as missing when the action is planned if they are required.
+/// Nothing is prompted for if stdin is not a terminal.
+pub(crate) fn prompt_for_missing_args(
+    config: &ProfileConfig,
+    profile_name: &str,
+    environment_name: Option<&str>,
+    args: &mut HashMap<String, String>,
+) {
+    if !std::io::stdin().is_terminal() {
+        return;
+    }
+
+    for (name, parameter) in missing_parameters(config, profile_name, environment_name, args) {
+        loop {
+            let value = read_value(name, parameter).unwrap_or_else(|err| {
+                log::error!("Failed to read arg {}: {}", name, err);
+                std::process::exit(1);
+            });
+            if value.is_empty() {
+                break;
+            }
+            match parameter.check(&value) {
+                Some(reason) => log::warn!("Invalid arg {}, {}", name, reason),
+                None => {
+                    args.insert(name.to_owned(), value);
+                    break;
+                },
+            }
+        }
+    }
+}
@@ -0,0 +1,184 @@
+use std::collections::{HashMap, HashSet};
+
+use libnprofile::profile::{ExecutionOptions, Profile, ProfileConfig, ProfileStatus};
+
+use crate::cli::OutputFormat;
+
+/// Status of a profile, including failures to determine it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+enum Status {
+    Enabled,
+    Disabled,
+    Unknown,
+    Error,
+}
+
+impl From<ProfileStatus> for Status {
+    fn from(value: ProfileStatus) -> Self {
+        match value {
+            ProfileStatus::Enabled => Status::Enabled,
+            ProfileStatus::Disabled => Status::Disabled,
+            ProfileStatus::Unknown => Status::Unknown,
+        }
+    }
+}
+
+impl std::fmt::Display for Status {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Status::Enabled => write!(f, "enabled"),
+            Status::Disabled => write!(f, "disabled"),
+            Status::Unknown => write!(f, "unknown"),
+            Status::Error => write!(f, "error"),
+        }
+    }
+}
+
+/// Status of a profile in an environment.
+#[derive(Clone, Debug, serde::Serialize)]
+struct StatusReport<'a> {
+    name: &'a str,
+    environment: Option<&'a str>,
+    status: Status,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    dependencies: Vec<StatusReport<'a>>,
+}
+
+impl StatusReport<'_> {
+    fn print(&self, indent: &str) {
+        let environment = self.environment.map(|environment| format!(" [{}]", environment)).unwrap_or_default();
+        let error = self.error.as_ref().map(|error| format!(": {}", error)).unwrap_or_default();
+        println!("{}{}{}\t{}{}", indent, self.name, environment, self.status, error);
+        for dependency in self.dependencies.iter() {
+            dependency.print(&format!("{}  ", indent));
+        }
+    }
+}
+
+/// Runs `is_enabled` commands, only once per profile and environment.
+struct StatusChecker<'a> {
+    environment_name: Option<&'a str>,
+    args: Option<&'a HashMap<String, String>>,
+    options: &'a ExecutionOptions,
+    cache: HashMap<(&'a str, &'a str), StatusReport<'a>>,
+}
+
+impl<'a> StatusChecker<'a> {
+    fn check(&mut self, profile: &'a Profile, environment_name: Option<&'a str>) -> StatusReport<'a> {
+        let environment_name = profile.choose_environment(environment_name.or(self.environment_name));
+        let (args, options) = (self.args, self.options);
+        self.cache
+            .entry((profile.name.as_str(), environment_name))
+            .or_insert_with(|| {
+                let (status, error) = match profile.is_enabled(environment_name, args, options) {
+                    Ok(status) => (Status::from(status), None),
+                    Err(err) => (Status::Error, Some(err.to_string())),
+                };
+                StatusReport {
+                    name: profile.name.as_str(),
+                    environment: Some(environment_name),
+                    status,
+                    error,
+                    dependencies: Vec::new(),
+                }
+            })
+            .clone()
+    }
+
+    /// Check a profile and its transitive dependencies.
+    ///
+    /// The status of a composition profile is derived from its dependencies: enabled if all are enabled,
+    /// otherwise error, disabled or unknown (in that order of precedence).
+    fn check_with_dependencies(&mut self, config: &'a ProfileConfig, profile: &'a Profile) -> StatusReport<'a> {
+        let dependencies = match config.resolve_dependencies(profile.name.as_str()) {
+            Ok(profiles) => profiles
+                .into_iter()
+                .filter(|(dependency, _)| !std::ptr::eq(*dependency, profile))
+                .map(|(dependency, environment_name)| self.check(dependency, environment_name))
+                .collect::<Vec<_>>(),
+            Err(err) => {
+                return StatusReport {
+                    name: profile.name.as_str(),
+                    environment: None,
+                    status: Status::Error,
+                    error: Some(err.to_string()),
+                    dependencies: Vec::new(),
+                };
+            },
+        };
+
+        if profile.is_composition_profile() {
+            let statuses = dependencies.iter().map(|dependency| dependency.status).collect::<Vec<_>>();
+            let status = if statuses.iter().all(|status| *status == Status::Enabled) {
+                Status::Enabled
+            } else {
+                [Status::Error, Status::Disabled]
+                    .into_iter()
+                    .find(|status| statuses.contains(status))
+                    .unwrap_or(Status::Unknown)
+            };
+            StatusReport { name: profile.name.as_str(), environment: None, status, error: None, dependencies }
+        } else {
+            StatusReport { dependencies, ..self.check(profile, None) }
+        }
+    }
+}
+
+/// Exit if an arg is not a parameter of any of the profiles (or their dependencies), before any commands are run.
+fn check_unknown_args(
+    config: &ProfileConfig,
+    profiles: &[&Profile],
+    environment_name: Option<&str>,
+    args: Option<&HashMap<String, String>>,
+) {
+    let mut parameters = HashSet::new();
+    for profile in profiles.iter() {
+        // Invalid dependencies are reported in the status of the profile
+        for (profile, dependency_environment_name) in config.resolve_dependencies(&profile.name).unwrap_or_default() {
+            let environment_name = profile.choose_environment(dependency_environment_name.or(environment_name));
+            let environment = profile.envs.as_ref().and_then(|envs| envs.get(environment_name));
+            parameters.extend(
+                environment
+                    .and_then(|environment| environment.parameters.as_ref())
+                    .into_iter()
+                    .flatten()
+                    .map(|(name, _)| name.as_str()),
+            );
+        }
+    }
+
+    let issues = libnprofile::validation::check_unknown_args(args, parameters);
+    if !issues.is_empty() {
+        log::error!("{}", libnprofile::error::Error::InvalidArgs { issues });
+        std::process::exit(1);
+    }
+}
+
+/// Report whether profiles are enabled by running their `is_enabled` commands.
+///
+/// If `profile` is `None`, reports the status of every profile in the config.
+pub(crate) fn report_status(
+    config: &ProfileConfig,
+    profile: Option<&Profile>,
+    environment_name: Option<&str>,
+    args: Option<&HashMap<String, String>>,
+    options: &ExecutionOptions,
+    output: OutputFormat,
+) {
+    let mut checker = StatusChecker { environment_name, args, options, cache: HashMap::new() };
+    let profiles = match profile {
+        Some(profile) => vec![profile],
+        None => config.profiles.iter().collect(),
+    };
+    check_unknown_args(config, &profiles, environment_name, args);
+    let reports =
+        profiles.into_iter().map(|profile| checker.check_with_dependencies(config, profile)).collect::<Vec<_>>();
+
+    match output {
+        OutputFormat::Text => reports.iter().for_each(|report| report.print("")),
+        OutputFormat::Json => crate::inspect::print_json(&reports),
+    }
+}